@@ -0,0 +1,167 @@
+//! Hand-annotated sets of truly relevant lines, for measuring slice quality as well
+//! as slice size.
+//!
+//! An annotation file is a JSON array like
+//!
+//! ```json
+//! [{
+//!   "file": "src/url.rs",
+//!   "start": 1532,
+//!   "end": 1536,
+//!   "relevant_lines": {"Backward": [37, 38, 49, 80], "Forward": [80]}
+//! }]
+//! ```
+//!
+//! where `start` and `end` are copied from the `range` of a result row, `file` is a
+//! suffix of its file name, and lines are 1-indexed. Directions without an
+//! annotation are not scored.
+
+use std::collections::BTreeSet;
+
+use flowistry::{infoflow::Direction, source_map::Range};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+  pub file: String,
+  pub start: usize,
+  pub end: usize,
+  pub relevant_lines: RelevantLines,
+}
+
+/// The annotated lines of each direction.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct RelevantLines {
+  pub forward: Option<BTreeSet<usize>>,
+  pub backward: Option<BTreeSet<usize>>,
+  pub both: Option<BTreeSet<usize>>,
+}
+
+impl RelevantLines {
+  pub fn get(&self, direction: Direction) -> Option<&BTreeSet<usize>> {
+    match direction {
+      Direction::Forward => self.forward.as_ref(),
+      Direction::Backward => self.backward.as_ref(),
+      Direction::Both => self.both.as_ref(),
+    }
+  }
+}
+
+impl Annotation {
+  fn matches(&self, range: &Range) -> bool {
+    range.start == self.start
+      && range.end == self.end
+      && range.filename.ends_with(&self.file)
+  }
+}
+
+/// The annotated relevant lines of the criterion at `range`, if any.
+pub fn find<'a>(
+  annotations: &'a [Annotation],
+  range: &Range,
+  direction: Direction,
+) -> Option<&'a BTreeSet<usize>> {
+  annotations
+    .iter()
+    .find(|annotation| annotation.matches(range))
+    .and_then(|annotation| annotation.relevant_lines.get(direction))
+}
+
+/// Precision, recall and F1 of a slice's lines against annotated lines. Precision is
+/// undefined for empty slices and recall for empty annotations.
+#[derive(Debug, Clone, Copy)]
+pub struct Quality {
+  pub precision: Option<f64>,
+  pub recall: Option<f64>,
+  pub f1: Option<f64>,
+}
+
+impl Quality {
+  pub fn compute(
+    slice_lines: &BTreeSet<usize>,
+    relevant_lines: &BTreeSet<usize>,
+  ) -> Self {
+    let true_positives = slice_lines.intersection(relevant_lines).count() as f64;
+    let ratio = |total: usize| (total > 0).then(|| true_positives / total as f64);
+    let precision = ratio(slice_lines.len());
+    let recall = ratio(relevant_lines.len());
+    let f1 = match (precision, recall) {
+      (Some(p), Some(r)) if p + r > 0. => Some(2. * p * r / (p + r)),
+      (Some(_), Some(_)) => Some(0.),
+      _ => None,
+    };
+    Quality {
+      precision,
+      recall,
+      f1,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn lines(lines: &[usize]) -> BTreeSet<usize> {
+    lines.iter().copied().collect()
+  }
+
+  #[test]
+  fn scores_partial_overlap() {
+    let quality = Quality::compute(&lines(&[1, 2, 3, 4]), &lines(&[3, 4, 5]));
+    assert_eq!(quality.precision, Some(0.5));
+    assert_eq!(quality.recall, Some(2. / 3.));
+    assert!((quality.f1.unwrap() - 4. / 7.).abs() < 1e-12);
+  }
+
+  #[test]
+  fn empty_sets_leave_scores_undefined() {
+    let quality = Quality::compute(&lines(&[]), &lines(&[1]));
+    assert_eq!(quality.precision, None);
+    assert_eq!(quality.recall, Some(0.));
+    assert_eq!(quality.f1, None);
+
+    let quality = Quality::compute(&lines(&[1]), &lines(&[]));
+    assert_eq!(quality.precision, Some(0.));
+    assert_eq!(quality.recall, None);
+    assert_eq!(quality.f1, None);
+  }
+
+  #[test]
+  fn disjoint_sets_have_zero_f1() {
+    let quality = Quality::compute(&lines(&[1]), &lines(&[2]));
+    assert_eq!(quality.f1, Some(0.));
+  }
+
+  #[test]
+  fn finds_annotations_by_range_and_direction() {
+    let annotations: Vec<Annotation> = serde_json::from_str(
+      r#"[{
+        "file": "src/url.rs",
+        "start": 10,
+        "end": 12,
+        "relevant_lines": {"Backward": [3, 4]}
+      }]"#,
+    )
+    .unwrap();
+    let range = Range {
+      start: 10,
+      end: 12,
+      filename: "/repo/src/url.rs".into(),
+    };
+    assert_eq!(
+      find(&annotations, &range, Direction::Backward),
+      Some(&lines(&[3, 4]))
+    );
+    assert_eq!(find(&annotations, &range, Direction::Forward), None);
+    let other = Range { start: 11, ..range };
+    assert_eq!(find(&annotations, &other, Direction::Backward), None);
+  }
+
+  #[test]
+  fn rejects_unknown_directions() {
+    let relevant_lines = serde_json::from_str::<RelevantLines>(r#"{"backward": [1]}"#);
+    assert!(relevant_lines.is_err());
+  }
+}
@@ -0,0 +1,197 @@
+//! Naive name-based slicers over a function's tokens, as a baseline for what an IDE
+//! offers without any dataflow analysis.
+//!
+//! Both baselines treat identifiers as plain names, so shadowing, fields of
+//! different structs and method names all alias.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use flowistry::infoflow::Direction;
+use rustc_data_structures::fx::FxHashSet as HashSet;
+use rustc_span::Symbol;
+
+fn names(
+  idents: &[Option<Symbol>],
+  indices: impl IntoIterator<Item = usize>,
+) -> HashSet<Symbol> {
+  indices.into_iter().filter_map(|idx| idents[idx]).collect()
+}
+
+/// Every token that is an identifier also appearing in the criterion, like an IDE
+/// highlighting all references to the names under the cursor.
+pub fn same_identifier(
+  idents: &[Option<Symbol>],
+  criterion: &HashSet<usize>,
+) -> HashSet<usize> {
+  let names = names(idents, criterion.iter().copied());
+  (0 .. idents.len())
+    .filter(|idx| {
+      criterion.contains(idx) || idents[*idx].map_or(false, |name| names.contains(&name))
+    })
+    .collect()
+}
+
+/// The transitive closure of statements that mention a name of interest, starting
+/// from every name in the criterion's statements, so that slicing from `b` in
+/// `b = a` reaches the statements defining `a`. Every name in an included statement
+/// becomes a name of interest. Backward slices only consider statements up to the criterion and
+/// forward slices only those from the criterion on.
+///
+/// `statements` assigns each token the index of its statement, as delimited by `;`
+/// and braces.
+pub fn def_use_closure(
+  idents: &[Option<Symbol>],
+  statements: &[usize],
+  criterion: &HashSet<usize>,
+  direction: Direction,
+) -> HashSet<usize> {
+  let criterion_statements = criterion
+    .iter()
+    .map(|idx| statements[*idx])
+    .collect::<BTreeSet<_>>();
+  let (first, last) = match (
+    criterion_statements.iter().next(),
+    criterion_statements.iter().next_back(),
+  ) {
+    (Some(first), Some(last)) => (*first, *last),
+    _ => return HashSet::default(),
+  };
+
+  let mut tokens_by_statement: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+  for (idx, statement) in statements.iter().enumerate() {
+    let in_scope = match direction {
+      Direction::Backward => *statement <= last,
+      Direction::Forward => *statement >= first,
+      Direction::Both => true,
+    };
+    if in_scope {
+      tokens_by_statement.entry(*statement).or_default().push(idx);
+    }
+  }
+
+  let mut names = names(
+    idents,
+    (0 .. idents.len()).filter(|idx| criterion_statements.contains(&statements[*idx])),
+  );
+  let mut included = criterion_statements;
+  loop {
+    let mut changed = false;
+    for (statement, tokens) in &tokens_by_statement {
+      if included.contains(statement) {
+        continue;
+      }
+      let mentions_name = tokens
+        .iter()
+        .any(|idx| idents[*idx].map_or(false, |name| names.contains(&name)));
+      if mentions_name {
+        included.insert(*statement);
+        names.extend(tokens.iter().filter_map(|idx| idents[*idx]));
+        changed = true;
+      }
+    }
+    if !changed {
+      break;
+    }
+  }
+
+  included
+    .iter()
+    .filter_map(|statement| tokens_by_statement.get(statement))
+    .flatten()
+    .copied()
+    .chain(criterion.iter().copied())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Words of `source` separated by spaces, with letters as identifiers and each
+  /// statement ending at `;`.
+  fn tokens(source: &str) -> (Vec<Option<Symbol>>, Vec<usize>) {
+    let mut statement = 0;
+    source
+      .split(' ')
+      .map(|word| {
+        let ident = word
+          .chars()
+          .all(char::is_alphabetic)
+          .then(|| Symbol::intern(word));
+        let token = (ident, statement);
+        if word == ";" {
+          statement += 1;
+        }
+        token
+      })
+      .unzip()
+  }
+
+  const SOURCE: &str = "a = 1 ; b = a ; c = 2 ; d = b ;";
+
+  fn slice(criterion: &[usize], direction: Direction) -> Vec<usize> {
+    rustc_span::create_default_session_globals_then(|| {
+      let (idents, statements) = tokens(SOURCE);
+      let criterion = criterion.iter().copied().collect();
+      let mut slice = def_use_closure(&idents, &statements, &criterion, direction)
+        .into_iter()
+        .collect::<Vec<_>>();
+      slice.sort_unstable();
+      slice
+    })
+  }
+
+  #[test]
+  fn same_identifier_matches_names() {
+    rustc_span::create_default_session_globals_then(|| {
+      let (idents, _) = tokens(SOURCE);
+      let criterion = [4].into_iter().collect();
+      let mut slice = same_identifier(&idents, &criterion)
+        .into_iter()
+        .collect::<Vec<_>>();
+      slice.sort_unstable();
+      assert_eq!(slice, vec![4, 14]);
+    });
+  }
+
+  #[test]
+  fn closes_over_names_transitively() {
+    let statements_0_1_3 = (0 .. 8).chain(12 .. 16).collect::<Vec<_>>();
+    assert_eq!(slice(&[0], Direction::Forward), statements_0_1_3);
+    assert_eq!(slice(&[12, 14], Direction::Backward), statements_0_1_3);
+  }
+
+  #[test]
+  fn seeds_from_the_whole_criterion_statement() {
+    // `b` alone never appears in `a = 1`, but the rest of `b = a` does.
+    assert_eq!(
+      slice(&[4], Direction::Backward),
+      (0 .. 8).collect::<Vec<_>>()
+    );
+    assert_eq!(
+      slice(&[12], Direction::Backward),
+      (0 .. 8).chain(12 .. 16).collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn stays_on_the_side_of_the_direction() {
+    assert_eq!(
+      slice(&[4], Direction::Forward),
+      (4 .. 8).chain(12 .. 16).collect::<Vec<_>>()
+    );
+    assert_eq!(
+      slice(&[4], Direction::Both),
+      (0 .. 8).chain(12 .. 16).collect::<Vec<_>>()
+    );
+    assert_eq!(
+      slice(&[8], Direction::Backward),
+      (8 .. 12).collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn empty_criterion_gives_empty_slice() {
+    assert!(slice(&[], Direction::Both).is_empty());
+  }
+}
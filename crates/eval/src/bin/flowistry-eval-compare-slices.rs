@@ -0,0 +1,34 @@
+//! Compares the slices recorded by two runs of the eval driver (see `SLICES_PATH`),
+//! e.g. one with `THREADS=1` and one with `THREADS=8`, and prints every criterion
+//! whose slice differs.
+
+use std::{env, fs, process::exit};
+
+use anyhow::{Context, Result};
+use flowistry_eval::determinism::{self, RecordedSlice};
+
+fn read_slices(path: &str) -> Result<Vec<RecordedSlice>> {
+  let contents = fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+  serde_json::from_str(&contents).with_context(|| format!("parsing {path}"))
+}
+
+fn main() -> Result<()> {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+  let (first, second) = match &args[..] {
+    [first, second] => (read_slices(first)?, read_slices(second)?),
+    _ => {
+      eprintln!("usage: flowistry-eval-compare-slices <first.json> <second.json>");
+      exit(2);
+    }
+  };
+
+  let differences = determinism::compare(&first, &second);
+  println!("{}", serde_json::to_string_pretty(&differences)?);
+
+  if !differences.is_empty() {
+    eprintln!("{} slices differ", differences.len());
+    exit(1);
+  }
+
+  Ok(())
+}
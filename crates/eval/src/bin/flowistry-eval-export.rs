@@ -0,0 +1,53 @@
+//! Converts a JSON result file written by the eval driver into flat tables, e.g.
+//! `flowistry-eval-export data/rayon.json data/rayon.csv data/rayon.parquet`.
+//!
+//! Rows are read and written in batches of [`export::BATCH_SIZE`], so result files of
+//! any size can be converted.
+
+use std::{env, process::exit};
+
+use anyhow::Result;
+use flowistry_eval::{
+  export::{self, ResultWriter},
+  schema,
+};
+
+fn main() -> Result<()> {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+  let (input, outputs) = match args.split_first() {
+    Some((input, outputs)) if !outputs.is_empty() => (input, outputs),
+    _ => {
+      eprintln!("usage: flowistry-eval-export <results.json> <output>...");
+      exit(2);
+    }
+  };
+
+  let mut writers = outputs
+    .iter()
+    .map(ResultWriter::create)
+    .collect::<Result<Vec<_>>>()?;
+  let mut write_batch = |batch: &[_]| -> Result<()> {
+    for writer in &mut writers {
+      writer.write(batch)?;
+    }
+    Ok(())
+  };
+
+  let mut batch = Vec::with_capacity(export::BATCH_SIZE);
+  schema::for_each_result(input, |result| {
+    batch.push(result);
+    if batch.len() == export::BATCH_SIZE {
+      write_batch(&batch)?;
+      batch.clear();
+    }
+    Ok(())
+  })?;
+  if !batch.is_empty() {
+    write_batch(&batch)?;
+  }
+
+  for writer in writers {
+    writer.finish()?;
+  }
+  Ok(())
+}
@@ -0,0 +1,37 @@
+//! Renders the slice size figures of the paper as SVG from result files named after
+//! their crate, e.g. `flowistry-eval-figures figures data/slices/*.json`. Only rows
+//! of the analysis configuration named by `CONFIG` are plotted, by default the
+//! modular one.
+
+use std::{env, fs, path::Path, process::exit};
+
+use anyhow::{Context, Result};
+use flowistry_eval::{figures, schema, AnalysisConfig};
+
+fn main() -> Result<()> {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+  let (out_dir, inputs) = match args.split_first() {
+    Some((out_dir, inputs)) if !inputs.is_empty() => (out_dir, inputs),
+    _ => {
+      eprintln!("usage: flowistry-eval-figures <out-dir> <results.json>...");
+      exit(2);
+    }
+  };
+
+  let mut crates = Vec::new();
+  for input in inputs {
+    let path = Path::new(input);
+    // Crates the driver failed on leave an empty file behind.
+    if fs::metadata(path)?.len() == 0 {
+      continue;
+    }
+    let crate_name = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .with_context(|| format!("no crate name in {input}"))?;
+    crates.push((crate_name.to_string(), schema::read_results(path)?));
+  }
+
+  let config = env::var("CONFIG").unwrap_or_else(|_| AnalysisConfig::modular().name);
+  figures::write_figures(&crates, &config, out_dir)
+}
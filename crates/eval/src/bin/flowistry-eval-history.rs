@@ -0,0 +1,188 @@
+//! Runs `cargo flowistry-eval` on a sequence of revisions of a repository and
+//! joins the results into a per-function time series, e.g.
+//!
+//! ```text
+//! flowistry-eval-history --repo data/repos/rayon --out data/history/rayon --tags -- --lib
+//! ```
+//!
+//! Each revision is checked out into a temporary git worktree. Results are written to
+//! `<out>/<revision>-<commit>.json`, where `<commit>` is the abbreviated commit hash
+//! so that revisions like `v1.0` and `v1_0` do not share a file. Revisions that
+//! already have results are skipped, so an interrupted run can be resumed. The time
+//! series is written to `<out>/history.json` and `<out>/history.csv`.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{App, Arg};
+use flowistry_eval::{export, history, schema};
+use log::{info, warn};
+
+fn git(repo: &Path, args: &[&str]) -> Result<String> {
+  let output = Command::new("git")
+    .arg("-C")
+    .arg(repo)
+    .args(args)
+    .output()
+    .context("could not run git")?;
+  if !output.status.success() {
+    bail!(
+      "git {} failed: {}",
+      args.join(" "),
+      String::from_utf8_lossy(&output.stderr)
+    );
+  }
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+fn evaluate_revision(
+  repo: &Path,
+  revision: &str,
+  output_path: &Path,
+  cargo_args: &[String],
+) -> Result<()> {
+  let worktree = output_path.with_extension("worktree");
+  let worktree_str = worktree.to_str().context("non-UTF-8 worktree path")?;
+  if worktree.exists() {
+    git(repo, &["worktree", "remove", "--force", worktree_str])?;
+  }
+  git(repo, &[
+    "worktree",
+    "add",
+    "--detach",
+    worktree_str,
+    revision,
+  ])?;
+
+  let status = Command::new("cargo")
+    .arg("flowistry-eval")
+    .args(cargo_args)
+    .current_dir(&worktree)
+    .env("OUTPUT_PATH", output_path)
+    .status()
+    .context("could not run cargo flowistry-eval");
+
+  git(repo, &["worktree", "remove", "--force", worktree_str])?;
+
+  let status = status?;
+  if !status.success() {
+    bail!("cargo flowistry-eval exited with {status}");
+  }
+  Ok(())
+}
+
+fn main() -> Result<()> {
+  env_logger::init();
+
+  let matches = App::new("flowistry-eval-history")
+    .about("Evaluates Focus Mode across the history of a repository")
+    .arg(
+      Arg::with_name("repo")
+        .long("repo")
+        .takes_value(true)
+        .required(true)
+        .help("Path to the git repository"),
+    )
+    .arg(
+      Arg::with_name("out")
+        .long("out")
+        .takes_value(true)
+        .required(true)
+        .help("Directory for per-revision results and the time series"),
+    )
+    .arg(
+      Arg::with_name("tags")
+        .long("tags")
+        .help("Evaluate every tag, oldest first"),
+    )
+    .arg(
+      Arg::with_name("tag-pattern")
+        .long("tag-pattern")
+        .takes_value(true)
+        .help("Only evaluate tags matching this glob, e.g. 'v1.*'"),
+    )
+    .arg(
+      Arg::with_name("revisions")
+        .multiple(true)
+        .help("Revisions to evaluate, in order; arguments after -- go to cargo"),
+    )
+    .get_matches_from(std::env::args().take_while(|arg| arg != "--"));
+  let cargo_args = std::env::args()
+    .skip_while(|arg| arg != "--")
+    .skip(1)
+    .collect::<Vec<_>>();
+
+  let repo = PathBuf::from(matches.value_of("repo").unwrap());
+  let out = PathBuf::from(matches.value_of("out").unwrap());
+  fs::create_dir_all(&out)?;
+  // The driver runs with the worktree as its working directory, so OUTPUT_PATH
+  // must not be relative.
+  let out =
+    fs::canonicalize(&out).with_context(|| format!("resolving {}", out.display()))?;
+
+  let mut revisions = matches
+    .values_of("revisions")
+    .map(|revisions| revisions.map(str::to_string).collect::<Vec<_>>())
+    .unwrap_or_default();
+  if matches.is_present("tags") {
+    let mut args = vec!["tag", "--list", "--sort=creatordate"];
+    if let Some(pattern) = matches.value_of("tag-pattern") {
+      args.push(pattern);
+    }
+    revisions.extend(git(&repo, &args)?.lines().map(str::to_string));
+  }
+  if revisions.is_empty() {
+    bail!("no revisions to evaluate, pass revisions or --tags");
+  }
+
+  let mut snapshots = Vec::new();
+  for (index, revision) in revisions.iter().enumerate() {
+    let commit = match git(&repo, &[
+      "rev-parse",
+      "--short=12",
+      &format!("{revision}^{{commit}}"),
+    ]) {
+      Ok(commit) => commit.trim().to_string(),
+      Err(e) => {
+        warn!("Skipping {revision}: {e:?}");
+        continue;
+      }
+    };
+    let file_name = revision.replace(|c: char| !c.is_alphanumeric() && c != '.', "_");
+    let output_path = out.join(format!("{file_name}-{commit}.json"));
+
+    if output_path.exists() {
+      info!("Reusing results for {revision}");
+    } else {
+      info!(
+        "Evaluating {revision} ({} / {})",
+        index + 1,
+        revisions.len()
+      );
+      if let Err(e) = evaluate_revision(&repo, revision, &output_path, &cargo_args) {
+        warn!("Skipping {revision}: {e:?}");
+        continue;
+      }
+    }
+
+    let results = match schema::read_results(&output_path) {
+      Ok(results) => results,
+      Err(e) => {
+        warn!("Skipping {revision}: {e:?}");
+        continue;
+      }
+    };
+    snapshots.extend(history::summarize_revision(revision, index, &results));
+  }
+
+  history::mark_changes(&mut snapshots);
+
+  fs::write(out.join("history.json"), serde_json::to_string(&snapshots)?)?;
+  export::write(out.join("history.csv"), &snapshots)?;
+
+  Ok(())
+}
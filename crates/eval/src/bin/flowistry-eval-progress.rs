@@ -0,0 +1,59 @@
+//! Summarizes the progress files written by parallel runs of the eval driver (see
+//! `PROGRESS_PATH`), e.g. `flowistry-eval-progress data/logs/*.progress.json`.
+
+use std::{env, fs};
+
+use anyhow::{Context, Result};
+use flowistry_eval::progress::ProgressSnapshot;
+
+fn main() -> Result<()> {
+  let mut snapshots = env::args()
+    .skip(1)
+    .map(|path| {
+      let json = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+      serde_json::from_str::<ProgressSnapshot>(&json)
+        .with_context(|| format!("parsing {path}"))
+    })
+    .collect::<Result<Vec<_>>>()?;
+  snapshots.sort_by(|a, b| a.crate_name.cmp(&b.crate_name));
+
+  let format_eta = |eta: Option<f64>| match eta {
+    Some(eta) => format!("{:.0}s", eta),
+    None => "?".into(),
+  };
+
+  for snapshot in &snapshots {
+    let status = if snapshot.finished {
+      "finished".to_string()
+    } else {
+      format!("ETA {}", format_eta(snapshot.eta))
+    };
+    println!(
+      "{:<20} {:>6}/{:<6} {:>4} failed  {:>8.0}s  {}",
+      snapshot.crate_name,
+      snapshot.done,
+      snapshot.total,
+      snapshot.failed,
+      snapshot.elapsed,
+      status
+    );
+  }
+
+  let sum = |f: fn(&ProgressSnapshot) -> usize| snapshots.iter().map(f).sum::<usize>();
+  let max_eta = snapshots
+    .iter()
+    .filter(|snapshot| !snapshot.finished)
+    .map(|snapshot| snapshot.eta)
+    .try_fold(0f64, |max, eta| eta.map(|eta| max.max(eta)));
+  println!(
+    "{:<20} {:>6}/{:<6} {:>4} failed  {} running, ETA {}",
+    "total",
+    sum(|s| s.done),
+    sum(|s| s.total),
+    sum(|s| s.failed),
+    snapshots.iter().filter(|s| !s.finished).count(),
+    format_eta(max_eta)
+  );
+
+  Ok(())
+}
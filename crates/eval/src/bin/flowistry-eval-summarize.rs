@@ -0,0 +1,116 @@
+//! Rolls up eval results by module or impl self type and prints the groups with the
+//! least focused slices, e.g.
+//!
+//! ```text
+//! flowistry-eval-summarize data/slices/rayon.json --by type --top 10
+//! ```
+//!
+//! With `--out`, the groups with at least `--min-criteria` criteria are also written
+//! as `.json` or any format supported by `flowistry-eval-export`, regardless of
+//! `--top`.
+
+use std::fs;
+
+use anyhow::Result;
+use clap::{App, Arg};
+use flowistry_eval::{
+  export,
+  rollup::{self, Grouping},
+  schema,
+};
+
+fn main() -> Result<()> {
+  let matches = App::new("flowistry-eval-summarize")
+    .about("Summarizes slice sizes by module or by type")
+    .arg(
+      Arg::with_name("by")
+        .long("by")
+        .takes_value(true)
+        .possible_values(&["module", "type"])
+        .default_value("module")
+        .help("What to group functions by"),
+    )
+    .arg(
+      Arg::with_name("top")
+        .long("top")
+        .takes_value(true)
+        .default_value("20")
+        .help("Number of groups to print per configuration and direction"),
+    )
+    .arg(
+      Arg::with_name("min-criteria")
+        .long("min-criteria")
+        .takes_value(true)
+        .default_value("10")
+        .help("Leave out groups with fewer slicing criteria than this"),
+    )
+    .arg(
+      Arg::with_name("out")
+        .long("out")
+        .takes_value(true)
+        .help("Write the groups kept by --min-criteria to this file"),
+    )
+    .arg(
+      Arg::with_name("results")
+        .multiple(true)
+        .required(true)
+        .help("Result files written by the eval driver"),
+    )
+    .get_matches();
+
+  let grouping = match matches.value_of("by").unwrap() {
+    "type" => Grouping::Type,
+    _ => Grouping::Module,
+  };
+  let top = matches.value_of("top").unwrap().parse::<usize>()?;
+  let min_criteria = matches.value_of("min-criteria").unwrap().parse::<usize>()?;
+
+  let mut results = Vec::new();
+  for path in matches.values_of("results").unwrap() {
+    results.extend(schema::read_results(path)?);
+  }
+
+  let summaries = rollup::summarize(&results, grouping)
+    .into_iter()
+    .filter(|summary| summary.num_criteria >= min_criteria)
+    .collect::<Vec<_>>();
+
+  if let Some(out) = matches.value_of("out") {
+    if out.ends_with(".json") {
+      fs::write(out, serde_json::to_string(&summaries)?)?;
+    } else {
+      export::write(out, &summaries)?;
+    }
+  }
+
+  let mut keys = summaries
+    .iter()
+    .map(|summary| (&summary.config, summary.direction as u8, summary.direction))
+    .collect::<Vec<_>>();
+  keys.sort_by_key(|(config, index, _)| (*config, *index));
+  keys.dedup_by_key(|(config, index, _)| (*config, *index));
+
+  for (config, _, direction) in keys {
+    println!("{config} / {direction:?}");
+    println!(
+      "  {:>6} {:>6} {:>6} {:>9}  group",
+      "lines", "tokens", "funcs", "criteria"
+    );
+    let rows = summaries
+      .iter()
+      .filter(|summary| &summary.config == config && summary.direction == direction);
+    for summary in rows.take(top) {
+      println!(
+        "  {:>5.1}% {:>5.1}% {:>6} {:>9}  {}",
+        summary.mean_relevant_line_fraction * 100.,
+        summary.mean_relevant_token_fraction * 100.,
+        summary.num_functions,
+        summary.num_criteria,
+        summary.group
+      );
+    }
+    println!();
+  }
+
+  Ok(())
+}
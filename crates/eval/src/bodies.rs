@@ -0,0 +1,210 @@
+//! Finding the function bodies of a crate that the eval analyzes.
+//!
+//! The HIR is traversed once by [`collect_bodies`], and the resulting list is shared
+//! by everything downstream: progress counting, `ONLY_RUN` filtering, sampling,
+//! queries and the analysis itself.
+
+use std::hash::{Hash, Hasher};
+
+use flowistry::source_map::Range;
+use rustc_data_structures::fx::FxHasher;
+use rustc_hir::{intravisit::Visitor, BodyId};
+use rustc_middle::{hir::nested_filter::OnlyBodies, ty::TyCtxt};
+use rustc_span::Span;
+
+use crate::stable_id;
+
+pub trait BodyVisitor<'tcx> {
+  fn visit(&mut self, body_span: Span, body_id: BodyId, tcx: TyCtxt<'tcx>);
+}
+
+struct BodyFinder<'tcx, 'a, V> {
+  pub tcx: TyCtxt<'tcx>,
+  pub visitor: &'a mut V,
+}
+
+impl<'tcx, V> Visitor<'tcx> for BodyFinder<'tcx, '_, V>
+where
+  V: BodyVisitor<'tcx>,
+{
+  type NestedFilter = OnlyBodies;
+
+  fn nested_visit_map(&mut self) -> Self::Map {
+    self.tcx.hir()
+  }
+
+  fn visit_nested_body(&mut self, id: BodyId) {
+    let hir = self.nested_visit_map();
+
+    // const/static items are considered to have bodies, so we want to exclude
+    // them from our search for functions
+    if !hir
+      .body_owner_kind(hir.body_owner_def_id(id))
+      .is_fn_or_closure()
+    {
+      return;
+    }
+
+    let owner = hir.body_owner(id);
+    let body_span = hir.span(owner);
+    if body_span.from_expansion() {
+      return;
+    }
+
+    self.visitor.visit(body_span, id, self.tcx);
+  }
+}
+
+pub fn visit_bodies<'tcx, V: BodyVisitor<'tcx>>(tcx: TyCtxt<'tcx>, visitor: &mut V) {
+  tcx
+    .hir()
+    .deep_visit_all_item_likes(&mut BodyFinder { tcx, visitor });
+}
+
+/// A function or closure body of the local crate that has source code available.
+#[derive(Debug, Clone)]
+pub struct CandidateBody {
+  /// 1-based position of the body in HIR traversal order, as used by `ONLY_RUN`.
+  pub index: usize,
+  pub body_id: BodyId,
+  /// Span of the whole item owning the body, including its signature.
+  pub span: Span,
+  pub function_range: Range,
+  /// `def_path_debug_str` of the body's owner.
+  pub function_path: String,
+  /// Order-independent path of the body's owner, see [`stable_id::stable_id`].
+  pub stable_id: String,
+  /// Whitespace-insensitive hash of the item's source.
+  pub content_hash: String,
+}
+
+#[derive(Default)]
+struct BodyCollector {
+  bodies: Vec<CandidateBody>,
+}
+
+impl BodyVisitor<'_> for BodyCollector {
+  fn visit(&mut self, body_span: Span, body_id: BodyId, tcx: TyCtxt) {
+    let source_map = tcx.sess.source_map();
+    let source_file = &source_map.lookup_source_file(body_span.lo());
+    if source_file.src.is_none() {
+      return;
+    }
+
+    let function_range = match Range::from_span(body_span, source_map) {
+      Ok(range) => range,
+      Err(_) => {
+        return;
+      }
+    };
+
+    let def_id = tcx.hir().body_owner_def_id(body_id).to_def_id();
+    self.bodies.push(CandidateBody {
+      index: self.bodies.len() + 1,
+      body_id,
+      span: body_span,
+      function_range,
+      function_path: tcx.def_path_debug_str(def_id),
+      stable_id: stable_id::stable_id(tcx, def_id),
+      content_hash: stable_id::content_hash(tcx, body_span),
+    });
+  }
+}
+
+/// Returns every body of the crate that the eval can analyze, in HIR order.
+pub fn collect_bodies(tcx: TyCtxt<'_>) -> Vec<CandidateBody> {
+  let mut collector = BodyCollector::default();
+  visit_bodies(tcx, &mut collector);
+  collector.bodies
+}
+
+/// Keeps only the body selected by an `ONLY_RUN` spec, which is either a body's
+/// index, its full function path or its stable ID.
+pub fn filter_only_run(bodies: Vec<CandidateBody>, spec: &str) -> Vec<CandidateBody> {
+  bodies
+    .into_iter()
+    .filter(|body| match spec.parse::<usize>() {
+      Ok(n) => body.index == n,
+      Err(_) => body.function_path == spec || body.stable_id == spec,
+    })
+    .collect()
+}
+
+/// Deterministically picks `n` bodies, preserving their order. The choice only
+/// depends on `seed` and each body's stable ID, so it is stable across runs.
+pub fn sample(bodies: Vec<CandidateBody>, n: usize, seed: u64) -> Vec<CandidateBody> {
+  sample_by(bodies, n, seed, |body| &body.stable_id)
+}
+
+/// Picks the `n` items whose IDs hash lowest under `seed`. Items with colliding
+/// hashes are picked in order.
+fn sample_by<T>(items: Vec<T>, n: usize, seed: u64, id: impl Fn(&T) -> &str) -> Vec<T> {
+  if items.len() <= n {
+    return items;
+  }
+
+  let mut keys = items
+    .iter()
+    .enumerate()
+    .map(|(index, item)| {
+      let mut hasher = FxHasher::default();
+      seed.hash(&mut hasher);
+      id(item).hash(&mut hasher);
+      (hasher.finish(), index)
+    })
+    .collect::<Vec<_>>();
+  keys.sort_unstable();
+  let mut picked = vec![false; items.len()];
+  for (_, index) in &keys[.. n] {
+    picked[*index] = true;
+  }
+
+  items
+    .into_iter()
+    .zip(picked)
+    .filter_map(|(item, picked)| picked.then(|| item))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ids(n: usize) -> Vec<String> {
+    (0 .. n).map(|i| format!("krate::f{i}")).collect()
+  }
+
+  fn sample_ids(items: Vec<String>, n: usize, seed: u64) -> Vec<String> {
+    sample_by(items, n, seed, |id| id)
+  }
+
+  #[test]
+  fn samples_nothing_for_zero() {
+    assert!(sample_ids(ids(10), 0, 0).is_empty());
+  }
+
+  #[test]
+  fn keeps_small_crates_whole() {
+    assert_eq!(sample_ids(ids(3), 5, 0), ids(3));
+  }
+
+  #[test]
+  fn picks_exactly_n_in_order() {
+    let sampled = sample_ids(ids(100), 10, 7);
+    assert_eq!(sampled.len(), 10);
+    let positions = sampled
+      .iter()
+      .map(|id| ids(100).iter().position(|other| other == id).unwrap())
+      .collect::<Vec<_>>();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(sample_ids(ids(100), 10, 7), sampled);
+  }
+
+  #[test]
+  fn breaks_ties_by_position() {
+    // Duplicate IDs hash equally, so only their position decides.
+    let items = vec!["a"; 5].into_iter().enumerate().collect::<Vec<_>>();
+    let sampled = sample_by(items, 2, 0, |(_, id)| id);
+    assert_eq!(sampled, vec![(0, "a"), (1, "a")]);
+  }
+}
@@ -0,0 +1,119 @@
+use anyhow::{bail, Context, Result};
+use flowistry::extensions::{ContextMode, EvalMode, MutabilityMode, PointerMode};
+use serde::Serialize;
+
+/// A named set of Flowistry analysis knobs that each body is evaluated under.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisConfig {
+  pub name: String,
+  pub eval_mode: EvalMode,
+}
+
+impl AnalysisConfig {
+  /// The configuration used in the paper: modular, mutability-aware, precise pointers.
+  pub fn modular() -> Self {
+    AnalysisConfig {
+      name: "modular".into(),
+      eval_mode: EvalMode {
+        mutability_mode: MutabilityMode::DistinguishMut,
+        context_mode: ContextMode::SigOnly,
+        pointer_mode: PointerMode::Precise,
+      },
+    }
+  }
+
+  /// Parses a spec of the form `name` or `name:key=value,key=value`, where keys are
+  /// `mutability` (`distinguish` | `ignore`), `context` (`sigonly` | `recurse`)
+  /// and `pointer` (`precise` | `conservative`). Unspecified keys keep the
+  /// modular defaults.
+  ///
+  /// Flowistry always includes control dependencies in its slices and has no knob
+  /// to turn them off, so a `control` key is rejected rather than ignored.
+  pub fn parse(spec: &str) -> Result<Self> {
+    let (name, knobs) = match spec.split_once(':') {
+      Some((name, knobs)) => (name, knobs),
+      None => (spec, ""),
+    };
+    let name = name.trim();
+    if name.is_empty() {
+      bail!("configuration `{}` has no name", spec);
+    }
+
+    let mut eval_mode = Self::modular().eval_mode;
+    for knob in knobs
+      .split(',')
+      .map(str::trim)
+      .filter(|knob| !knob.is_empty())
+    {
+      let (key, value) = knob
+        .split_once('=')
+        .with_context(|| format!("expected key=value, found `{knob}`"))?;
+      match (key.trim(), value.trim()) {
+        ("mutability", "distinguish") => {
+          eval_mode.mutability_mode = MutabilityMode::DistinguishMut
+        }
+        ("mutability", "ignore") => eval_mode.mutability_mode = MutabilityMode::IgnoreMut,
+        ("context", "sigonly") => eval_mode.context_mode = ContextMode::SigOnly,
+        ("context", "recurse") => eval_mode.context_mode = ContextMode::Recurse,
+        ("pointer", "precise") => eval_mode.pointer_mode = PointerMode::Precise,
+        ("pointer", "conservative") => eval_mode.pointer_mode = PointerMode::Conservative,
+        ("control", _) => bail!(
+          "configuration `{}` sets `{}`, but Flowistry does not support disabling \
+           control dependence",
+          name,
+          knob
+        ),
+        (key, value) => bail!(
+          "unknown setting `{}={}` in configuration `{}`",
+          key,
+          value,
+          name
+        ),
+      }
+    }
+
+    Ok(AnalysisConfig {
+      name: name.to_string(),
+      eval_mode,
+    })
+  }
+
+  /// Reads a `;`-separated list of specs from the `CONFIGS` environment variable,
+  /// falling back to only the modular configuration.
+  pub fn from_env() -> Result<Vec<Self>> {
+    let configs = match std::env::var("CONFIGS") {
+      Ok(specs) => specs
+        .split(';')
+        .filter(|spec| !spec.trim().is_empty())
+        .map(Self::parse)
+        .collect::<Result<Vec<_>>>()?,
+      Err(_) => vec![Self::modular()],
+    };
+
+    for (i, config) in configs.iter().enumerate() {
+      if configs[.. i].iter().any(|other| other.name == config.name) {
+        bail!("duplicate configuration name `{}`", config.name);
+      }
+    }
+
+    Ok(configs)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rejects_control_dependence_knob() {
+    let err = AnalysisConfig::parse("nocontrol:control=off").unwrap_err();
+    assert!(err.to_string().contains("control dependence"), "{err}");
+  }
+
+  #[test]
+  fn rejects_unknown_knobs() {
+    assert!(AnalysisConfig::parse("x:pointer=fuzzy").is_err());
+    assert!(AnalysisConfig::parse("x:pointer").is_err());
+    assert!(AnalysisConfig::parse(":pointer=precise").is_err());
+  }
+}
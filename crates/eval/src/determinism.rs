@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use flowistry::{infoflow::Direction, source_map::Range};
+use flowistry_ide::focus::PlaceInfo;
+use serde::{Deserialize, Serialize};
+
+use crate::visitor::direction_slice;
+
+/// The raw slice computed for one criterion, in a canonical order so that two runs
+/// can be compared range-by-range.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedSlice {
+  pub function_path: String,
+  pub config: String,
+  pub range: Range,
+  pub direction: Direction,
+  pub slice: Vec<Range>,
+}
+
+impl RecordedSlice {
+  pub fn from_place_info(
+    function_path: &str,
+    config: &str,
+    place_info: &[PlaceInfo],
+  ) -> Vec<Self> {
+    place_info
+      .iter()
+      .flat_map(|info| {
+        [Direction::Forward, Direction::Backward, Direction::Both]
+          .into_iter()
+          .map(move |direction| {
+            let mut slice = direction_slice(info, direction).to_vec();
+            slice.sort_by(|a, b| {
+              (&a.filename, a.start, a.end).cmp(&(&b.filename, b.start, b.end))
+            });
+            slice.dedup();
+            RecordedSlice {
+              function_path: function_path.to_string(),
+              config: config.to_string(),
+              range: info.range.clone(),
+              direction,
+              slice,
+            }
+          })
+      })
+      .collect()
+  }
+
+  fn key(&self) -> (&str, &str, &str, usize, usize, u8) {
+    (
+      &self.function_path,
+      &self.config,
+      &self.range.filename,
+      self.range.start,
+      self.range.end,
+      self.direction as u8,
+    )
+  }
+}
+
+/// A criterion whose slice was not reproduced by a second analysis of the same body.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Nondeterminism {
+  /// The criterion was only analyzed in one of the two runs.
+  MissingSlice {
+    function_path: String,
+    config: String,
+    range: Range,
+    direction: Direction,
+    in_first: bool,
+  },
+  /// Both runs analyzed the criterion, but produced different slices.
+  SliceMismatch {
+    function_path: String,
+    config: String,
+    range: Range,
+    direction: Direction,
+    only_in_first: Vec<Range>,
+    only_in_second: Vec<Range>,
+  },
+}
+
+fn missing(slice: &RecordedSlice, in_first: bool) -> Nondeterminism {
+  Nondeterminism::MissingSlice {
+    function_path: slice.function_path.clone(),
+    config: slice.config.clone(),
+    range: slice.range.clone(),
+    direction: slice.direction,
+    in_first,
+  }
+}
+
+/// Compares two sets of slices of the same crate, returning every criterion where
+/// they disagree.
+pub fn compare(first: &[RecordedSlice], second: &[RecordedSlice]) -> Vec<Nondeterminism> {
+  let second_by_key = second
+    .iter()
+    .map(|slice| (slice.key(), slice))
+    .collect::<HashMap<_, _>>();
+  let first_by_key = first
+    .iter()
+    .map(|slice| (slice.key(), slice))
+    .collect::<HashMap<_, _>>();
+
+  let mut differences = Vec::new();
+  for slice in first {
+    let other = match second_by_key.get(&slice.key()) {
+      Some(other) => other,
+      None => {
+        differences.push(missing(slice, true));
+        continue;
+      }
+    };
+
+    let only_in = |a: &RecordedSlice, b: &RecordedSlice| {
+      a.slice
+        .iter()
+        .filter(|range| !b.slice.contains(range))
+        .cloned()
+        .collect::<Vec<_>>()
+    };
+    let only_in_first = only_in(slice, other);
+    let only_in_second = only_in(other, slice);
+    if !only_in_first.is_empty() || !only_in_second.is_empty() {
+      differences.push(Nondeterminism::SliceMismatch {
+        function_path: slice.function_path.clone(),
+        config: slice.config.clone(),
+        range: slice.range.clone(),
+        direction: slice.direction,
+        only_in_first,
+        only_in_second,
+      });
+    }
+  }
+
+  differences.extend(
+    second
+      .iter()
+      .filter(|slice| !first_by_key.contains_key(&slice.key()))
+      .map(|slice| missing(slice, false)),
+  );
+
+  differences
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn range(start: usize, end: usize) -> Range {
+    Range {
+      start,
+      end,
+      filename: "lib.rs".into(),
+    }
+  }
+
+  fn slice(start: usize, direction: Direction, slice: Vec<Range>) -> RecordedSlice {
+    RecordedSlice {
+      function_path: "krate::f".into(),
+      config: "modular".into(),
+      range: range(start, start + 1),
+      direction,
+      slice,
+    }
+  }
+
+  #[test]
+  fn identical_runs_agree() {
+    let run = vec![
+      slice(0, Direction::Forward, vec![range(0, 5)]),
+      slice(0, Direction::Backward, vec![]),
+    ];
+    assert!(compare(&run, &run.clone()).is_empty());
+  }
+
+  #[test]
+  fn directions_are_compared_separately() {
+    let first = vec![slice(0, Direction::Forward, vec![range(0, 5)])];
+    let second = vec![slice(0, Direction::Backward, vec![range(0, 5)])];
+    let differences = compare(&first, &second);
+    assert_eq!(differences.len(), 2);
+    assert!(matches!(differences[0], Nondeterminism::MissingSlice {
+      in_first: true,
+      ..
+    }));
+    assert!(matches!(differences[1], Nondeterminism::MissingSlice {
+      in_first: false,
+      ..
+    }));
+  }
+
+  #[test]
+  fn mismatches_list_ranges_on_each_side() {
+    let first = vec![slice(0, Direction::Both, vec![range(0, 5), range(6, 8)])];
+    let second = vec![slice(0, Direction::Both, vec![range(0, 5), range(9, 12)])];
+    match &compare(&first, &second)[..] {
+      [Nondeterminism::SliceMismatch {
+        only_in_first,
+        only_in_second,
+        ..
+      }] => {
+        assert_eq!(only_in_first, &[range(6, 8)]);
+        assert_eq!(only_in_second, &[range(9, 12)]);
+      }
+      differences => panic!("expected one mismatch, found {differences:?}"),
+    }
+  }
+}
@@ -0,0 +1,540 @@
+//! Flat tabular exports of eval results, for tools that cannot afford to load the
+//! full JSON array. Nested objects like `Range` are flattened into one column per
+//! field, joined with `_` (e.g. `function_range_start`).
+//!
+//! [`ResultWriter`] writes [`EvalResult`]s in fixed-size batches with a declared
+//! column per field, so exports of any size only hold one batch in memory.
+//! [`write`] flattens any serializable rows through JSON, for small derived tables
+//! like history and rollup summaries.
+
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use flowistry::infoflow::Direction;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::schema::EvalResult;
+
+/// Number of rows written at a time by [`ResultWriter`].
+pub const BATCH_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+  Int,
+  Float,
+  Bool,
+  Str,
+}
+
+/// One value of a column, named after its [`ColumnType`].
+enum Cell<'a> {
+  Int(Option<i64>),
+  Float(Option<f64>),
+  Bool(Option<bool>),
+  Str(Option<&'a str>),
+}
+
+impl Cell<'_> {
+  fn to_csv(&self) -> String {
+    match self {
+      Cell::Int(Some(n)) => n.to_string(),
+      Cell::Float(Some(x)) => x.to_string(),
+      Cell::Bool(Some(b)) => b.to_string(),
+      Cell::Str(Some(s)) => s.to_string(),
+      _ => String::new(),
+    }
+  }
+}
+
+struct Column {
+  name: &'static str,
+  #[cfg_attr(not(feature = "arrow"), allow(dead_code))]
+  ty: ColumnType,
+  get: for<'a> fn(&'a EvalResult) -> Cell<'a>,
+}
+
+macro_rules! column {
+  ($name:literal, $ty:ident, |$r:ident| $get:expr) => {
+    Column {
+      name: $name,
+      ty: ColumnType::$ty,
+      get: |$r| Cell::$ty($get),
+    }
+  };
+}
+
+fn count(n: usize) -> Option<i64> {
+  Some(n as i64)
+}
+
+fn optional_count(n: Option<usize>) -> Option<i64> {
+  n.map(|n| n as i64)
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+  match direction {
+    Direction::Forward => "Forward",
+    Direction::Backward => "Backward",
+    Direction::Both => "Both",
+  }
+}
+
+/// The columns of an exported [`EvalResult`], in the order of its fields.
+fn result_columns() -> Vec<Column> {
+  vec![
+    column!("schema_version", Int, |r| Some(r.schema_version as i64)),
+    column!("function_range_start", Int, |r| count(
+      r.function_range.start
+    )),
+    column!("function_range_end", Int, |r| count(r.function_range.end)),
+    column!("function_range_filename", Str, |r| Some(
+      &r.function_range.filename
+    )),
+    column!("function_path", Str, |r| Some(&r.function_path)),
+    column!("stable_id", Str, |r| r.stable_id.as_deref()),
+    column!("content_hash", Str, |r| r.content_hash.as_deref()),
+    column!("num_instructions", Int, |r| count(r.num_instructions)),
+    column!("num_basic_blocks", Int, |r| {
+      optional_count(r.features.as_ref().map(|f| f.num_basic_blocks))
+    }),
+    column!("num_loops", Int, |r| {
+      optional_count(r.features.as_ref().map(|f| f.num_loops))
+    }),
+    column!("cyclomatic_complexity", Int, |r| {
+      optional_count(r.features.as_ref().map(|f| f.cyclomatic_complexity))
+    }),
+    column!("num_calls", Int, |r| {
+      optional_count(r.features.as_ref().map(|f| f.num_calls))
+    }),
+    column!("num_locals", Int, |r| {
+      optional_count(r.features.as_ref().map(|f| f.num_locals))
+    }),
+    column!("num_borrows", Int, |r| {
+      optional_count(r.features.as_ref().map(|f| f.num_borrows))
+    }),
+    column!("has_unsafe", Bool, |r| r
+      .features
+      .as_ref()
+      .map(|f| f.has_unsafe)),
+    column!("num_tokens", Int, |r| count(r.num_tokens)),
+    column!("num_lines", Int, |r| count(r.num_lines)),
+    column!("num_statements", Int, |r| optional_count(r.num_statements)),
+    column!("range_start", Int, |r| count(r.range.start)),
+    column!("range_end", Int, |r| count(r.range.end)),
+    column!("range_filename", Str, |r| Some(&r.range.filename)),
+    column!("config", Str, |r| Some(&r.config)),
+    column!("direction", Str, |r| Some(direction_name(r.direction))),
+    column!("num_relevant_tokens", Int, |r| count(r.num_relevant_tokens)),
+    column!("num_relevant_lines", Int, |r| count(r.num_relevant_lines)),
+    column!("num_relevant_statements", Int, |r| {
+      optional_count(r.num_relevant_statements)
+    }),
+    column!("line_iqr", Int, |r| count(r.line_iqr)),
+    column!("max_line_distance", Int, |r| optional_count(
+      r.max_line_distance
+    )),
+    column!("mean_line_distance", Float, |r| r.mean_line_distance),
+    column!("num_relevant_lines_before", Int, |r| {
+      optional_count(r.num_relevant_lines_before)
+    }),
+    column!("num_relevant_lines_after", Int, |r| {
+      optional_count(r.num_relevant_lines_after)
+    }),
+    column!("precision", Float, |r| r.precision),
+    column!("recall", Float, |r| r.recall),
+    column!("f1", Float, |r| r.f1),
+    column!("duration", Float, |r| Some(r.duration)),
+    column!("num_relevant_tokens_recursive", Int, |r| {
+      optional_count(r.num_relevant_tokens_recursive)
+    }),
+    column!("num_relevant_lines_recursive", Int, |r| {
+      optional_count(r.num_relevant_lines_recursive)
+    }),
+    column!("recursive_duration", Float, |r| r.recursive_duration),
+    column!("num_unmatched_recursive", Int, |r| {
+      optional_count(r.num_unmatched_recursive)
+    }),
+    column!("num_baseline_identifier_tokens", Int, |r| {
+      optional_count(r.num_baseline_identifier_tokens)
+    }),
+    column!("num_baseline_identifier_lines", Int, |r| {
+      optional_count(r.num_baseline_identifier_lines)
+    }),
+    column!("num_baseline_def_use_tokens", Int, |r| {
+      optional_count(r.num_baseline_def_use_tokens)
+    }),
+    column!("num_baseline_def_use_lines", Int, |r| {
+      optional_count(r.num_baseline_def_use_lines)
+    }),
+  ]
+}
+
+#[cfg(feature = "arrow")]
+mod arrow_batches {
+  use std::sync::Arc;
+
+  use anyhow::Result;
+  use arrow::{
+    array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    record_batch::RecordBatch,
+  };
+
+  use super::{Cell, Column, ColumnType};
+  use crate::schema::EvalResult;
+
+  fn data_type(ty: ColumnType) -> DataType {
+    match ty {
+      ColumnType::Int => DataType::Int64,
+      ColumnType::Float => DataType::Float64,
+      ColumnType::Bool => DataType::Boolean,
+      ColumnType::Str => DataType::Utf8,
+    }
+  }
+
+  pub fn schema(columns: &[Column]) -> SchemaRef {
+    Arc::new(Schema::new(
+      columns
+        .iter()
+        .map(|column| Field::new(column.name, data_type(column.ty), true))
+        .collect(),
+    ))
+  }
+
+  fn array(column: &Column, rows: &[EvalResult]) -> ArrayRef {
+    let cells = rows.iter().map(|row| (column.get)(row));
+    macro_rules! collect {
+      ($variant:ident, $array:ty) => {
+        Arc::new(<$array>::from(
+          cells
+            .map(|cell| match cell {
+              Cell::$variant(value) => value,
+              _ => unreachable!("column {} has the wrong type", column.name),
+            })
+            .collect::<Vec<_>>(),
+        ))
+      };
+    }
+    match column.ty {
+      ColumnType::Int => collect!(Int, Int64Array),
+      ColumnType::Float => collect!(Float, Float64Array),
+      ColumnType::Bool => collect!(Bool, BooleanArray),
+      ColumnType::Str => collect!(Str, StringArray),
+    }
+  }
+
+  pub fn record_batch(
+    schema: &SchemaRef,
+    columns: &[Column],
+    rows: &[EvalResult],
+  ) -> Result<RecordBatch> {
+    let arrays = columns.iter().map(|column| array(column, rows)).collect();
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+  }
+}
+
+enum Sink {
+  Csv(csv::Writer<BufWriter<File>>),
+  #[cfg(feature = "arrow")]
+  ArrowIpc(arrow::ipc::writer::FileWriter<BufWriter<File>>),
+  #[cfg(feature = "arrow")]
+  Parquet(parquet::arrow::ArrowWriter<File>),
+}
+
+/// Writes [`EvalResult`]s to a CSV, Arrow IPC or Parquet file, one batch at a time.
+pub struct ResultWriter {
+  columns: Vec<Column>,
+  #[cfg(feature = "arrow")]
+  schema: arrow::datatypes::SchemaRef,
+  sink: Sink,
+}
+
+fn create(path: &Path) -> Result<File> {
+  File::create(path).with_context(|| format!("creating {}", path.display()))
+}
+
+impl ResultWriter {
+  /// Creates a writer for the format implied by the extension of `path`: `.csv`,
+  /// `.arrow` (Arrow IPC) or `.parquet`. The latter two require the `arrow` feature.
+  pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let columns = result_columns();
+    #[cfg(feature = "arrow")]
+    let schema = arrow_batches::schema(&columns);
+    let sink = match path.extension().and_then(|ext| ext.to_str()) {
+      Some("csv") => {
+        let mut writer = csv::Writer::from_writer(BufWriter::new(create(path)?));
+        writer.write_record(columns.iter().map(|column| column.name))?;
+        Sink::Csv(writer)
+      }
+      #[cfg(feature = "arrow")]
+      Some("arrow" | "ipc" | "feather") => Sink::ArrowIpc(
+        arrow::ipc::writer::FileWriter::try_new(BufWriter::new(create(path)?), &schema)?,
+      ),
+      #[cfg(feature = "arrow")]
+      Some("parquet") => Sink::Parquet(parquet::arrow::ArrowWriter::try_new(
+        create(path)?,
+        schema.clone(),
+        None,
+      )?),
+      _ => bail!(
+        "unsupported export format for {} (is the `arrow` feature enabled?)",
+        path.display()
+      ),
+    };
+    Ok(ResultWriter {
+      columns,
+      #[cfg(feature = "arrow")]
+      schema,
+      sink,
+    })
+  }
+
+  /// Appends `rows`, which should be at most [`BATCH_SIZE`] long to bound memory.
+  pub fn write(&mut self, rows: &[EvalResult]) -> Result<()> {
+    match &mut self.sink {
+      Sink::Csv(writer) => {
+        for row in rows {
+          writer
+            .write_record(self.columns.iter().map(|column| (column.get)(row).to_csv()))?;
+        }
+      }
+      #[cfg(feature = "arrow")]
+      Sink::ArrowIpc(writer) => {
+        writer.write(&arrow_batches::record_batch(
+          &self.schema,
+          &self.columns,
+          rows,
+        )?)?;
+      }
+      #[cfg(feature = "arrow")]
+      Sink::Parquet(writer) => {
+        writer.write(&arrow_batches::record_batch(
+          &self.schema,
+          &self.columns,
+          rows,
+        )?)?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn finish(self) -> Result<()> {
+    match self.sink {
+      Sink::Csv(mut writer) => writer.flush()?,
+      #[cfg(feature = "arrow")]
+      Sink::ArrowIpc(mut writer) => writer.finish()?,
+      #[cfg(feature = "arrow")]
+      Sink::Parquet(writer) => {
+        writer.close()?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Writes `results` to `path` in batches of [`BATCH_SIZE`], see
+/// [`ResultWriter::create`] for the formats.
+pub fn write_results(path: impl AsRef<Path>, results: &[EvalResult]) -> Result<()> {
+  let mut writer = ResultWriter::create(path)?;
+  for batch in results.chunks(BATCH_SIZE) {
+    writer.write(batch)?;
+  }
+  writer.finish()
+}
+
+/// A table of flattened rows, with columns taken from the union of all rows.
+pub struct FlatTable {
+  pub columns: Vec<String>,
+  pub rows: Vec<Map<String, Value>>,
+}
+
+fn flatten_into(prefix: &str, value: Value, row: &mut Map<String, Value>) {
+  match value {
+    Value::Object(fields) => {
+      for (key, value) in fields {
+        let key = if prefix.is_empty() {
+          key
+        } else {
+          format!("{prefix}_{key}")
+        };
+        flatten_into(&key, value, row);
+      }
+    }
+    Value::Array(items) => {
+      row.insert(
+        prefix.to_string(),
+        Value::String(Value::Array(items).to_string()),
+      );
+    }
+    value => {
+      row.insert(prefix.to_string(), value);
+    }
+  }
+}
+
+impl FlatTable {
+  pub fn new<T: Serialize>(items: &[T]) -> Result<Self> {
+    let mut columns: Vec<String> = Vec::new();
+    let mut column_indices: HashMap<String, usize> = HashMap::new();
+    let rows = items
+      .iter()
+      .map(|item| {
+        let mut row = Map::new();
+        flatten_into("", serde_json::to_value(item)?, &mut row);
+        for key in row.keys() {
+          if !column_indices.contains_key(key) {
+            column_indices.insert(key.clone(), columns.len());
+            columns.push(key.clone());
+          }
+        }
+        Ok(row)
+      })
+      .collect::<Result<Vec<_>>>()?;
+    Ok(FlatTable { columns, rows })
+  }
+
+  #[cfg(feature = "arrow")]
+  fn column<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+    self
+      .rows
+      .iter()
+      .map(move |row| row.get(name).unwrap_or(&Value::Null))
+  }
+
+  pub fn write_csv(&self, writer: impl Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(&self.columns)?;
+    for row in &self.rows {
+      writer.write_record(self.columns.iter().map(|column| {
+        match row.get(column).unwrap_or(&Value::Null) {
+          Value::Null => String::new(),
+          Value::String(s) => s.clone(),
+          value => value.to_string(),
+        }
+      }))?;
+    }
+    writer.flush()?;
+    Ok(())
+  }
+
+  /// Converts the table to a record batch, inferring each column's type from its
+  /// values. Columns without any values are declared as strings.
+  #[cfg(feature = "arrow")]
+  pub fn to_record_batch(&self) -> Result<arrow::record_batch::RecordBatch> {
+    use std::sync::Arc;
+
+    use arrow::{
+      array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray},
+      datatypes::{DataType, Field, Schema},
+      record_batch::RecordBatch,
+    };
+
+    let (fields, arrays): (Vec<_>, Vec<_>) = self
+      .columns
+      .iter()
+      .map(|name| {
+        let values = self.column(name).collect::<Vec<_>>();
+        let non_null = || values.iter().filter(|value| !value.is_null());
+        let has_values = non_null().next().is_some();
+        let (data_type, array): (_, ArrayRef) =
+          if has_values && non_null().all(|v| v.is_boolean()) {
+            let array = values.iter().map(|v| v.as_bool()).collect::<Vec<_>>();
+            (DataType::Boolean, Arc::new(BooleanArray::from(array)))
+          } else if has_values && non_null().all(|v| v.is_i64()) {
+            let array = values.iter().map(|v| v.as_i64()).collect::<Vec<_>>();
+            (DataType::Int64, Arc::new(Int64Array::from(array)))
+          } else if has_values && non_null().all(|v| v.is_number()) {
+            let array = values.iter().map(|v| v.as_f64()).collect::<Vec<_>>();
+            (DataType::Float64, Arc::new(Float64Array::from(array)))
+          } else {
+            let array = values
+              .iter()
+              .map(|v| match v {
+                Value::Null => None,
+                Value::String(s) => Some(s.clone()),
+                v => Some(v.to_string()),
+              })
+              .collect::<Vec<_>>();
+            (DataType::Utf8, Arc::new(StringArray::from(array)))
+          };
+        (Field::new(name, data_type, true), array)
+      })
+      .unzip();
+
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+  }
+
+  #[cfg(feature = "arrow")]
+  pub fn write_arrow_ipc(&self, writer: impl Write) -> Result<()> {
+    let batch = self.to_record_batch()?;
+    let mut writer = arrow::ipc::writer::FileWriter::try_new(writer, &batch.schema())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(())
+  }
+
+  #[cfg(feature = "arrow")]
+  pub fn write_parquet(&self, file: File) -> Result<()> {
+    let batch = self.to_record_batch()?;
+    let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+  }
+}
+
+/// Writes `items` to `path` in the format implied by its extension: `.csv`,
+/// `.arrow` (Arrow IPC) or `.parquet`. The latter two require the `arrow` feature.
+///
+/// The whole table is built in memory, so large result files should be written with
+/// [`ResultWriter`] instead.
+pub fn write<T: Serialize>(path: impl AsRef<Path>, items: &[T]) -> Result<()> {
+  let path = path.as_ref();
+  let table = FlatTable::new(items)?;
+  match path.extension().and_then(|ext| ext.to_str()) {
+    Some("csv") => table.write_csv(BufWriter::new(create(path)?)),
+    #[cfg(feature = "arrow")]
+    Some("arrow" | "ipc" | "feather") => table.write_arrow_ipc(create(path)?),
+    #[cfg(feature = "arrow")]
+    Some("parquet") => table.write_parquet(create(path)?),
+    _ => bail!(
+      "unsupported export format for {} (is the `arrow` feature enabled?)",
+      path.display()
+    ),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use super::*;
+  use crate::schema::{self, SCHEMA_VERSION};
+
+  #[test]
+  fn columns_match_serialized_fields() {
+    let result: EvalResult =
+      serde_json::from_value(schema::tests::row(SCHEMA_VERSION)).unwrap();
+    let columns = result_columns()
+      .iter()
+      .map(|column| column.name.to_string())
+      .collect::<Vec<_>>();
+    let unique = columns.iter().collect::<HashSet<_>>();
+    assert_eq!(
+      unique.len(),
+      columns.len(),
+      "duplicate columns in {columns:?}"
+    );
+
+    let table = FlatTable::new(&[result]).unwrap();
+    let fields = table.columns.iter().collect::<HashSet<_>>();
+    assert_eq!(unique, fields);
+  }
+}
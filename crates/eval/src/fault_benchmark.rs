@@ -0,0 +1,390 @@
+//! A fault-localization benchmark driven by failing tests.
+//!
+//! Each benchmark names a crate's known buggy lines and a failing `#[test]`. We slice
+//! backward from the operands of the test's assertions, then from the return values
+//! of every local function called within that slice, and so on up to a maximum call
+//! depth. A programmer is modeled as reading the sliced lines breadth-first by call
+//! depth, and within a function from the last line upward. The rank of the first
+//! buggy line in that order gives the EXAM score, the fraction of the crate's lines
+//! read before finding the bug.
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use anyhow::{anyhow, Result};
+use flowistry::{infoflow::Direction, source_map::ToSpan};
+use log::warn;
+use rustc_hir::{
+  intravisit::{self, Visitor},
+  BodyId, Expr, ExprKind,
+};
+use rustc_middle::ty::{TyCtxt, TypeckResults};
+use rustc_span::{
+  hygiene::{ExpnKind, MacroKind},
+  Span,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  bodies::{self, CandidateBody},
+  fault_localization::BugLocation,
+  utils::{fraction, span_lines},
+  visitor::direction_slice,
+};
+
+fn default_max_depth() -> usize {
+  2
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultBenchmark {
+  pub name: String,
+  pub bug: BugLocation,
+  /// Stable ID or function path of the failing test, or a suffix of its `::`
+  /// segments.
+  pub test: String,
+  /// How many calls deep to follow the slice from the test.
+  #[serde(default = "default_max_depth")]
+  pub max_depth: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkScore {
+  pub name: String,
+  pub test_path: String,
+  pub num_assertions: usize,
+  /// Number of functions whose slices were read, including the test.
+  pub num_functions: usize,
+  /// Number of distinct lines across all slices.
+  pub num_slice_lines: usize,
+  /// Number of lines in all function bodies of the crate.
+  pub num_program_lines: usize,
+  /// 1-based position of the first buggy line in reading order, if it is sliced.
+  pub bug_rank: Option<usize>,
+  /// Call depth of the function where the bug was found, the test being 0.
+  pub bug_depth: Option<usize>,
+  /// `bug_rank / num_program_lines`.
+  pub exam_score: Option<f64>,
+  /// `bug_rank / num_slice_lines`.
+  pub slice_exam_score: Option<f64>,
+}
+
+/// Collects the operands of `assert*!` macros in a body, i.e. the expressions written
+/// by the test's author inside each assertion, such as `a` and `b` in
+/// `assert_eq!(a, b)`, including the arguments of a custom panic message.
+#[derive(Default)]
+struct AssertionFinder {
+  call_sites: Vec<Span>,
+  operands: Vec<Span>,
+  /// Call site of the assertion whose expansion is being visited.
+  assertion: Option<Span>,
+}
+
+impl<'tcx> Visitor<'tcx> for AssertionFinder {
+  fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+    let expn_data = expr.span.ctxt().outer_expn_data();
+    if let ExpnKind::Macro(MacroKind::Bang, name) = expn_data.kind {
+      if name.as_str().starts_with("assert") {
+        let call_site = expn_data.call_site;
+        if !self.call_sites.contains(&call_site) {
+          self.call_sites.push(call_site);
+        }
+        let outer = self.assertion.replace(call_site);
+        intravisit::walk_expr(self, expr);
+        self.assertion = outer;
+        return;
+      }
+    }
+
+    // Operands keep the context of the call site, unlike the code the macro adds
+    // around them.
+    if let Some(call_site) = self.assertion {
+      if expr.span.ctxt() == call_site.ctxt() && call_site.contains(expr.span) {
+        self.operands.push(expr.span);
+        let outer = self.assertion.take();
+        intravisit::walk_expr(self, expr);
+        self.assertion = outer;
+        return;
+      }
+    }
+
+    intravisit::walk_expr(self, expr);
+  }
+}
+
+/// Collects the spans of the values a body returns: its tail expression and the
+/// operands of `return`.
+#[derive(Default)]
+struct ReturnFinder {
+  spans: Vec<Span>,
+}
+
+impl<'tcx> Visitor<'tcx> for ReturnFinder {
+  fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+    if let ExprKind::Ret(Some(value)) = expr.kind {
+      self.spans.push(value.span);
+    }
+    intravisit::walk_expr(self, expr);
+  }
+}
+
+fn return_spans(tcx: TyCtxt<'_>, body_id: BodyId) -> Vec<Span> {
+  let body = tcx.hir().body(body_id);
+  let mut finder = ReturnFinder::default();
+  finder.visit_body(body);
+  let value = &body.value;
+  match value.kind {
+    ExprKind::Block(block, _) => finder.spans.extend(block.expr.map(|tail| tail.span)),
+    _ => finder.spans.push(value.span),
+  }
+  finder.spans
+}
+
+/// Collects local functions called by a body, with the span of each call.
+struct CallFinder<'tcx> {
+  typeck: &'tcx TypeckResults<'tcx>,
+  calls: Vec<(rustc_hir::def_id::DefId, Span)>,
+}
+
+impl<'tcx> Visitor<'tcx> for CallFinder<'tcx> {
+  fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+    let callee = match expr.kind {
+      ExprKind::Call(func, _) => match &func.kind {
+        ExprKind::Path(qpath) => self.typeck.qpath_res(qpath, func.hir_id).opt_def_id(),
+        _ => None,
+      },
+      ExprKind::MethodCall(..) => self.typeck.type_dependent_def_id(expr.hir_id),
+      _ => None,
+    };
+    if let Some(def_id) = callee {
+      self.calls.push((def_id, expr.span));
+    }
+    intravisit::walk_expr(self, expr);
+  }
+}
+
+/// Unions the backward slices of every place in `body` that overlaps a criterion.
+fn backward_slice(
+  tcx: TyCtxt<'_>,
+  body: &CandidateBody,
+  criteria: &[Span],
+) -> Result<Vec<Span>> {
+  fluid_let::fluid_set!(flowistry_ide::FOCUS_DEBUG, true);
+  let focus = flowistry_ide::focus(tcx, body.body_id)?;
+  let mut slice = Vec::new();
+  for info in &focus.place_info {
+    let span = info.range.to_span(tcx)?;
+    if criteria.iter().any(|criterion| criterion.overlaps(span)) {
+      for range in direction_slice(info, Direction::Backward) {
+        slice.push(range.to_span(tcx)?);
+      }
+    }
+  }
+  Ok(slice)
+}
+
+/// A line of source, identified by file name and 1-based line number.
+type Line = (String, usize);
+
+/// Whether `path` names the test `test`, either exactly or by a suffix of whole path
+/// segments.
+fn names_test(path: &str, test: &str) -> bool {
+  path == test || path.ends_with(&format!("::{test}"))
+}
+
+/// The order in which a programmer reads the sliced lines of each function, given
+/// with its call depth in breadth-first order: function by function, each from its
+/// last line upward, skipping lines already read. Also returns the 1-based rank and
+/// the depth of the first line for which `is_bug` holds.
+fn reading_order(
+  slices: impl IntoIterator<Item = (usize, BTreeSet<Line>)>,
+  is_bug: impl Fn(&Line) -> bool,
+) -> (Vec<Line>, Option<(usize, usize)>) {
+  let mut read_lines = Vec::new();
+  let mut seen_lines = HashSet::new();
+  let mut bug = None;
+  for (depth, lines) in slices {
+    for line in lines.into_iter().rev() {
+      if seen_lines.insert(line.clone()) {
+        if bug.is_none() && is_bug(&line) {
+          bug = Some((read_lines.len() + 1, depth));
+        }
+        read_lines.push(line);
+      }
+    }
+  }
+  (read_lines, bug)
+}
+
+fn run_benchmark(
+  tcx: TyCtxt<'_>,
+  benchmark: &FaultBenchmark,
+  bodies: &[CandidateBody],
+) -> Result<BenchmarkScore> {
+  let test = bodies
+    .iter()
+    .find(|body| {
+      names_test(&body.stable_id, &benchmark.test)
+        || names_test(&body.function_path, &benchmark.test)
+    })
+    .ok_or_else(|| anyhow!("no function matching `{}`", benchmark.test))?;
+
+  let mut assertions = AssertionFinder::default();
+  assertions.visit_body(tcx.hir().body(test.body_id));
+  if assertions.call_sites.is_empty() {
+    return Err(anyhow!("{} has no assertions", test.function_path));
+  }
+
+  let is_bug = |(file, line): &Line| {
+    file.ends_with(&benchmark.bug.file)
+      && (benchmark.bug.start_line ..= benchmark.bug.end_line).contains(line)
+  };
+
+  let mut slices = Vec::new();
+  let mut visited = HashSet::new();
+  let mut queue = VecDeque::from([(test, assertions.operands.clone(), 0)]);
+  while let Some((body, criteria, depth)) = queue.pop_front() {
+    if !visited.insert(body.body_id) {
+      continue;
+    }
+
+    let slice = match backward_slice(tcx, body, &criteria) {
+      Ok(slice) => slice,
+      Err(e) => {
+        warn!("Could not slice {}: {e:?}", body.function_path);
+        continue;
+      }
+    };
+
+    let body_lines = slice
+      .iter()
+      .flat_map(|span| span_lines(tcx, *span))
+      .collect::<BTreeSet<_>>();
+    slices.push((depth, body_lines));
+
+    if depth == benchmark.max_depth {
+      continue;
+    }
+    let mut calls = CallFinder {
+      typeck: tcx.typeck_body(body.body_id),
+      calls: Vec::new(),
+    };
+    calls.visit_body(tcx.hir().body(body.body_id));
+    for (def_id, call_span) in calls.calls {
+      if !slice.iter().any(|span| span.overlaps(call_span)) {
+        continue;
+      }
+      let callee = bodies.iter().find(|candidate| {
+        tcx.hir().body_owner_def_id(candidate.body_id).to_def_id() == def_id
+      });
+      if let Some(callee) = callee {
+        queue.push_back((callee, return_spans(tcx, callee.body_id), depth + 1));
+      }
+    }
+  }
+
+  let num_program_lines = bodies
+    .iter()
+    .flat_map(|body| span_lines(tcx, body.span))
+    .collect::<HashSet<_>>()
+    .len();
+  let (read_lines, bug) = reading_order(slices, is_bug);
+
+  Ok(BenchmarkScore {
+    name: benchmark.name.clone(),
+    test_path: test.function_path.clone(),
+    num_assertions: assertions.call_sites.len(),
+    num_functions: visited.len(),
+    num_slice_lines: read_lines.len(),
+    num_program_lines,
+    bug_rank: bug.map(|(rank, _)| rank),
+    bug_depth: bug.map(|(_, depth)| depth),
+    exam_score: bug.map(|(rank, _)| fraction(rank, num_program_lines)),
+    slice_exam_score: bug.map(|(rank, _)| fraction(rank, read_lines.len())),
+  })
+}
+
+/// Scores every benchmark. Benchmarks whose test cannot be found or has no
+/// assertions are logged and skipped.
+pub fn run_benchmarks(
+  tcx: TyCtxt<'_>,
+  benchmarks: &[FaultBenchmark],
+) -> Vec<BenchmarkScore> {
+  let bodies = bodies::collect_bodies(tcx);
+  benchmarks
+    .iter()
+    .filter_map(|benchmark| match run_benchmark(tcx, benchmark, &bodies) {
+      Ok(score) => Some(score),
+      Err(e) => {
+        warn!("Skipping benchmark {}: {e:?}", benchmark.name);
+        None
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::source::compile_source;
+
+  fn lines(lines: &[usize]) -> BTreeSet<Line> {
+    lines
+      .iter()
+      .map(|line| ("lib.rs".to_string(), *line))
+      .collect()
+  }
+
+  #[test]
+  fn reads_functions_in_order_from_the_bottom() {
+    let slices = [
+      (0, lines(&[3, 5, 4])),
+      (1, lines(&[10, 11])),
+      (1, lines(&[20])),
+    ];
+    let (read, bug) = reading_order(slices, |(_, line)| *line == 10);
+    let read = read.into_iter().map(|(_, line)| line).collect::<Vec<_>>();
+    assert_eq!(read, [5, 4, 3, 11, 10, 20]);
+    assert_eq!(bug, Some((5, 1)));
+  }
+
+  #[test]
+  fn ranks_lines_by_first_read() {
+    let slices = [(0, lines(&[1, 2])), (1, lines(&[2, 3]))];
+    let (read, bug) = reading_order(slices, |(_, line)| *line == 2);
+    assert_eq!(read.len(), 3);
+    assert_eq!(bug, Some((1, 0)));
+
+    let (_, bug) = reading_order([(0, lines(&[1, 2]))], |(_, line)| *line == 7);
+    assert_eq!(bug, None);
+  }
+
+  #[test]
+  fn names_tests_by_whole_segments() {
+    assert!(names_test("krate::tests::parses", "parses"));
+    assert!(names_test("krate::tests::parses", "tests::parses"));
+    assert!(names_test("parses", "parses"));
+    assert!(!names_test("krate::tests::reparses", "parses"));
+    assert!(!names_test("krate::tests::parses", "ests::parses"));
+  }
+
+  #[test]
+  fn finds_assertion_operands() {
+    let source = "fn f(x: u32) -> u32 { x }
+      fn t() { let a = 1; assert_eq!(f(a), 2); assert!(a > 0, \"a is {}\", a); }";
+    let (num_assertions, operands) = compile_source(source, |tcx| {
+      let test = bodies::collect_bodies(tcx).pop().unwrap();
+      let mut assertions = AssertionFinder::default();
+      assertions.visit_body(tcx.hir().body(test.body_id));
+      let source_map = tcx.sess.source_map();
+      let operands = assertions
+        .operands
+        .iter()
+        .map(|span| source_map.span_to_snippet(*span).unwrap())
+        .collect::<Vec<_>>();
+      (assertions.call_sites.len(), operands)
+    })
+    .unwrap();
+    assert_eq!(num_assertions, 2);
+    assert_eq!(operands, ["f(a)", "2", "a > 0", "\"a is {}\"", "a"]);
+  }
+}
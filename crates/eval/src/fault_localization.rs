@@ -0,0 +1,123 @@
+//! Fault localization on programs with known bugs, such as the user-study tasks.
+//!
+//! A task names the lines of a seeded bug and the criteria a programmer would start
+//! debugging from, e.g. the value a failing assertion compares. For each criterion
+//! and direction, we report whether the slice reaches the bug and how many lines a
+//! programmer would have to read.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use flowistry::{infoflow::Direction, source_map::ToSpan};
+use log::warn;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  query::{self, QueryTarget},
+  utils::span_lines,
+  visitor::direction_slice,
+};
+
+/// An inclusive range of 1-indexed lines in a source file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BugLocation {
+  pub file: String,
+  pub start_line: usize,
+  pub end_line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultTask {
+  pub task: String,
+  pub bug: BugLocation,
+  /// Criteria in the `file:line:column` or `file:start-end` syntax of `QUERY`.
+  pub criteria: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultLocalization {
+  pub task: String,
+  pub criterion: String,
+  pub function_path: String,
+  pub direction: Direction,
+  /// Whether any line of the bug is in the slice.
+  pub bug_in_slice: bool,
+  pub num_bug_lines_in_slice: usize,
+  /// Number of lines containing part of the slice.
+  pub num_slice_lines: usize,
+  /// Number of lines of the function containing the criterion.
+  pub num_function_lines: usize,
+}
+
+fn lines(tcx: TyCtxt<'_>, span: Span) -> impl Iterator<Item = usize> {
+  span_lines(tcx, span).map(|(_, line)| line)
+}
+
+fn localize_criterion(
+  tcx: TyCtxt<'_>,
+  task: &FaultTask,
+  criterion: &str,
+) -> Result<Vec<FaultLocalization>> {
+  let target: QueryTarget = criterion.parse()?;
+  let resolved = query::find_criterion(tcx, &target)?;
+
+  // The bug only counts as found if the slice is in the bug's file.
+  let source_map = tcx.sess.source_map();
+  let in_bug_file = |span: Span| {
+    let file = source_map.lookup_source_file(span.lo());
+    file
+      .name
+      .prefer_local()
+      .to_string()
+      .ends_with(&task.bug.file)
+  };
+  let bug_lines = task.bug.start_line ..= task.bug.end_line;
+
+  [Direction::Forward, Direction::Backward, Direction::Both]
+    .into_iter()
+    .map(|direction| {
+      let slice = direction_slice(&resolved.place_info, direction)
+        .iter()
+        .map(|range| range.to_span(tcx))
+        .collect::<Result<Vec<_>>>()?;
+      let slice_lines = slice
+        .iter()
+        .flat_map(|span| lines(tcx, *span))
+        .collect::<BTreeSet<_>>();
+      let bug_lines_in_slice = slice
+        .iter()
+        .filter(|span| in_bug_file(**span))
+        .flat_map(|span| lines(tcx, *span))
+        .filter(|line| bug_lines.contains(line))
+        .collect::<BTreeSet<_>>();
+
+      Ok(FaultLocalization {
+        task: task.task.clone(),
+        criterion: criterion.to_string(),
+        function_path: resolved.body.function_path.clone(),
+        direction,
+        bug_in_slice: !bug_lines_in_slice.is_empty(),
+        num_bug_lines_in_slice: bug_lines_in_slice.len(),
+        num_slice_lines: slice_lines.len(),
+        num_function_lines: lines(tcx, resolved.body.span).count(),
+      })
+    })
+    .collect()
+}
+
+/// Slices from every criterion of every task. Criteria that cannot be resolved are
+/// logged and skipped.
+pub fn localize(tcx: TyCtxt<'_>, tasks: &[FaultTask]) -> Vec<FaultLocalization> {
+  let mut results = Vec::new();
+  for task in tasks {
+    for criterion in &task.criteria {
+      match localize_criterion(tcx, task, criterion) {
+        Ok(rows) => results.extend(rows),
+        Err(e) => warn!("Skipping {} criterion {criterion}: {e:?}", task.task),
+      }
+    }
+  }
+  results
+}
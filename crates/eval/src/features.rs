@@ -0,0 +1,127 @@
+use rustc_data_structures::fx::FxHashSet as HashSet;
+use rustc_middle::mir::{
+  visit::Visitor, BasicBlock, Body, ClearCrossCrate, Location, Rvalue, Safety,
+  TerminatorKind,
+};
+use serde::{Deserialize, Serialize};
+
+/// MIR-level structural features of a function, used to relate slice sizes to
+/// code complexity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionFeatures {
+  pub num_basic_blocks: usize,
+  pub num_loops: usize,
+  pub cyclomatic_complexity: usize,
+  pub num_calls: usize,
+  pub num_locals: usize,
+  pub num_borrows: usize,
+  pub has_unsafe: bool,
+}
+
+#[derive(Default)]
+struct BorrowCounter {
+  num_borrows: usize,
+}
+
+impl<'tcx> Visitor<'tcx> for BorrowCounter {
+  fn visit_rvalue(&mut self, rvalue: &Rvalue<'tcx>, location: Location) {
+    if let Rvalue::Ref(..) | Rvalue::AddressOf(..) = rvalue {
+      self.num_borrows += 1;
+    }
+    self.super_rvalue(rvalue, location);
+  }
+}
+
+/// Successors of a block along normal control flow, leaving out unwind edges and
+/// the imaginary edges of `FalseEdge` and `FalseUnwind`.
+fn normal_successors(kind: &TerminatorKind) -> Vec<BasicBlock> {
+  match kind {
+    TerminatorKind::Goto { target }
+    | TerminatorKind::Drop { target, .. }
+    | TerminatorKind::DropAndReplace { target, .. }
+    | TerminatorKind::Assert { target, .. }
+    | TerminatorKind::FalseEdge {
+      real_target: target,
+      ..
+    }
+    | TerminatorKind::FalseUnwind {
+      real_target: target,
+      ..
+    } => vec![*target],
+    TerminatorKind::SwitchInt { targets, .. } => targets.all_targets().to_vec(),
+    TerminatorKind::Call { destination, .. } => {
+      destination.iter().map(|(_, target)| *target).collect()
+    }
+    TerminatorKind::InlineAsm { destination, .. } => {
+      destination.iter().copied().collect()
+    }
+    TerminatorKind::Yield { resume, drop, .. } => {
+      std::iter::once(*resume).chain(*drop).collect()
+    }
+    TerminatorKind::Resume
+    | TerminatorKind::Abort
+    | TerminatorKind::Return
+    | TerminatorKind::Unreachable
+    | TerminatorKind::GeneratorDrop => Vec::new(),
+  }
+}
+
+impl FunctionFeatures {
+  pub fn compute(body: &Body) -> Self {
+    // Cleanup blocks only run while unwinding, so they are left out of the CFG
+    // along with the edges into them.
+    let blocks = body.basic_blocks();
+    let num_basic_blocks = blocks.iter().filter(|data| !data.is_cleanup).count();
+
+    let edges = blocks
+      .iter_enumerated()
+      .filter(|(_, data)| !data.is_cleanup)
+      .flat_map(|(block, data)| {
+        normal_successors(&data.terminator().kind)
+          .into_iter()
+          .map(move |successor| (block, successor))
+      })
+      .collect::<Vec<_>>();
+
+    // A loop is identified by its header, the target of one or more back edges,
+    // i.e. edges into a block that dominates the edge's source.
+    let dominators = body.dominators();
+    let num_loops = edges
+      .iter()
+      .filter(|(from, to)| dominators.is_dominated_by(*from, *to))
+      .map(|(_, header)| *header)
+      .collect::<HashSet<_>>()
+      .len();
+
+    // McCabe's E - N + 2P over the CFG, with P = 1 for a single function.
+    let cyclomatic_complexity = (edges.len() + 2).saturating_sub(num_basic_blocks);
+
+    let num_calls = blocks
+      .iter()
+      .filter(|data| matches!(data.terminator().kind, TerminatorKind::Call { .. }))
+      .count();
+
+    let mut borrow_counter = BorrowCounter::default();
+    borrow_counter.visit_body(body);
+
+    let has_unsafe = body
+      .source_scopes
+      .iter()
+      .any(|scope| match &scope.local_data {
+        ClearCrossCrate::Set(data) => {
+          matches!(data.safety, Safety::FnUnsafe | Safety::ExplicitUnsafe(_))
+        }
+        ClearCrossCrate::Clear => false,
+      });
+
+    FunctionFeatures {
+      num_basic_blocks,
+      num_loops,
+      cyclomatic_complexity,
+      num_calls,
+      num_locals: body.local_decls.len(),
+      num_borrows: borrow_counter.num_borrows,
+      has_unsafe,
+    }
+  }
+}
@@ -0,0 +1,459 @@
+//! The slice size figures of `slice-size-analysis.ipynb` (Figure 1 of the paper),
+//! rendered as SVG box plots without needing Python, seaborn or LaTeX.
+//!
+//! Boxes follow seaborn's conventions: they span the first to third quartile, and
+//! whiskers extend to the furthest value within 1.5 IQR of the box, with values
+//! beyond drawn as fliers.
+
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::Result;
+use flowistry::infoflow::Direction;
+
+use crate::{
+  schema::EvalResult,
+  utils::{fraction, to_xml_string},
+};
+
+/// Seaborn's default `tab10` palette.
+const PALETTE: [&str; 4] = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728"];
+const FONT: &str = "font-family=\"serif\" font-size=\"10\"";
+
+/// One slice of one crate.
+struct Sample<'a> {
+  crate_name: &'a str,
+  direction: Direction,
+  num_lines: usize,
+  line_fraction: f64,
+  token_fraction: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoxStats {
+  pub q1: f64,
+  pub median: f64,
+  pub q3: f64,
+  pub whisker_low: f64,
+  pub whisker_high: f64,
+  pub fliers: Vec<f64>,
+}
+
+/// The `q`-th quantile of sorted `values`, interpolated linearly like numpy.
+fn quantile(values: &[f64], q: f64) -> f64 {
+  let position = q * (values.len() - 1) as f64;
+  let (low, high) = (position.floor() as usize, position.ceil() as usize);
+  values[low] + (values[high] - values[low]) * (position - low as f64)
+}
+
+impl BoxStats {
+  pub fn compute(mut values: Vec<f64>) -> Option<Self> {
+    if values.is_empty() {
+      return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let q1 = quantile(&values, 0.25);
+    let q3 = quantile(&values, 0.75);
+    let (low, high) = (q1 - 1.5 * (q3 - q1), q3 + 1.5 * (q3 - q1));
+    let inside = || {
+      values
+        .iter()
+        .copied()
+        .filter(|v| (low ..= high).contains(v))
+    };
+    let mut fliers = values
+      .iter()
+      .copied()
+      .filter(|v| !(low ..= high).contains(v))
+      .collect::<Vec<_>>();
+    // Fliers at the same value would be drawn on top of each other.
+    fliers.dedup();
+    Some(BoxStats {
+      q1,
+      median: quantile(&values, 0.5),
+      q3,
+      whisker_low: inside().fold(q1, f64::min),
+      whisker_high: inside().fold(q3, f64::max),
+      fliers,
+    })
+  }
+}
+
+/// A box plot of values in `[0, 1]`, with one group of boxes per category and one
+/// box per hue within each group.
+pub struct BoxPlot {
+  /// Width and height in inches, as passed to `plt.figure(figsize=...)`.
+  pub size: (f64, f64),
+  pub x_label: String,
+  pub y_label: String,
+  pub categories: Vec<String>,
+  pub hues: Vec<String>,
+  /// Indexed by category, then hue.
+  pub boxes: Vec<Vec<Option<BoxStats>>>,
+  pub show_fliers: bool,
+  pub show_legend: bool,
+  /// Fraction of each category's width taken up by its boxes.
+  pub box_width: f64,
+}
+
+impl BoxPlot {
+  pub fn to_svg(&self) -> String {
+    let (width, height) = (self.size.0 * 72., self.size.1 * 72.);
+    let legend = self.show_legend && self.hues.len() > 1;
+    let (left, right) = (45., if legend { width - 80. } else { width - 10. });
+    let (top, bottom) = (10., height - 35.);
+    let y = |v: f64| bottom - v.clamp(0., 1.) * (bottom - top);
+    let band = (right - left) / self.categories.len().max(1) as f64;
+    let box_width = band * self.box_width / self.hues.len().max(1) as f64;
+
+    let mut svg = String::new();
+    writeln!(
+      svg,
+      "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}pt\" height=\"{height}pt\" \
+       viewBox=\"0 0 {width} {height}\">"
+    )
+    .unwrap();
+    writeln!(
+      svg,
+      "<rect width=\"{width}\" height=\"{height}\" fill=\"white\"/>"
+    )
+    .unwrap();
+
+    for tick in 0 ..= 5 {
+      let value = tick as f64 / 5.;
+      writeln!(
+        svg,
+        "<line x1=\"{left}\" x2=\"{right}\" y1=\"{0:.2}\" y2=\"{0:.2}\" stroke=\"#ebebeb\"/>",
+        y(value)
+      )
+      .unwrap();
+      writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{:.2}\" text-anchor=\"end\" dominant-baseline=\"middle\" \
+         {FONT}>{value:.1}</text>",
+        left - 4.,
+        y(value)
+      )
+      .unwrap();
+    }
+    writeln!(
+      svg,
+      "<text transform=\"translate(12 {:.2}) rotate(-90)\" text-anchor=\"middle\" \
+       {FONT}>{}</text>",
+      (top + bottom) / 2.,
+      to_xml_string(&self.y_label)
+    )
+    .unwrap();
+
+    for (i, category) in self.categories.iter().enumerate() {
+      let center = left + band * (i as f64 + 0.5);
+      writeln!(
+        svg,
+        "<text x=\"{center:.2}\" y=\"{}\" text-anchor=\"middle\" {FONT}>{}</text>",
+        bottom + 13.,
+        to_xml_string(category)
+      )
+      .unwrap();
+
+      let group_left = center - box_width * self.hues.len() as f64 / 2.;
+      for (j, stats) in self.boxes[i].iter().enumerate() {
+        let stats = match stats {
+          Some(stats) => stats,
+          None => continue,
+        };
+        let x = group_left + box_width * (j as f64 + 0.5);
+        let (x0, x1) = (x - box_width * 0.4, x + box_width * 0.4);
+        let (cap0, cap1) = (x - box_width * 0.2, x + box_width * 0.2);
+        // Like seaborn, boxes without hues are colored by category.
+        let color = PALETTE[if self.hues.len() > 1 { j } else { i } % PALETTE.len()];
+        let stroke = "stroke=\"#3f3f3f\" stroke-width=\"1\"";
+        for (from, to) in [
+          (stats.whisker_low, stats.q1),
+          (stats.q3, stats.whisker_high),
+        ] {
+          writeln!(
+            svg,
+            "<line x1=\"{x:.2}\" x2=\"{x:.2}\" y1=\"{:.2}\" y2=\"{:.2}\" {stroke}/>",
+            y(from),
+            y(to)
+          )
+          .unwrap();
+        }
+        for end in [stats.whisker_low, stats.whisker_high] {
+          writeln!(
+            svg,
+            "<line x1=\"{cap0:.2}\" x2=\"{cap1:.2}\" y1=\"{0:.2}\" y2=\"{0:.2}\" {stroke}/>",
+            y(end)
+          )
+          .unwrap();
+        }
+        writeln!(
+          svg,
+          "<rect x=\"{x0:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" \
+           fill=\"{color}\" {stroke}/>",
+          y(stats.q3),
+          x1 - x0,
+          y(stats.q1) - y(stats.q3)
+        )
+        .unwrap();
+        writeln!(
+          svg,
+          "<line x1=\"{x0:.2}\" x2=\"{x1:.2}\" y1=\"{0:.2}\" y2=\"{0:.2}\" {stroke}/>",
+          y(stats.median)
+        )
+        .unwrap();
+        if self.show_fliers {
+          for flier in &stats.fliers {
+            writeln!(
+              svg,
+              "<circle cx=\"{x:.2}\" cy=\"{:.2}\" r=\"2\" fill=\"none\" stroke=\"#3f3f3f\"/>",
+              y(*flier)
+            )
+            .unwrap();
+          }
+        }
+      }
+    }
+    writeln!(
+      svg,
+      "<text x=\"{:.2}\" y=\"{}\" text-anchor=\"middle\" {FONT}>{}</text>",
+      (left + right) / 2.,
+      height - 6.,
+      to_xml_string(&self.x_label)
+    )
+    .unwrap();
+
+    if legend {
+      for (j, hue) in self.hues.iter().enumerate() {
+        let y = top + 15. * j as f64;
+        writeln!(
+          svg,
+          "<rect x=\"{}\" y=\"{y}\" width=\"10\" height=\"10\" fill=\"{}\"/>",
+          right + 10.,
+          PALETTE[j % PALETTE.len()]
+        )
+        .unwrap();
+        writeln!(
+          svg,
+          "<text x=\"{}\" y=\"{}\" dominant-baseline=\"middle\" {FONT}>{}</text>",
+          right + 25.,
+          y + 5.,
+          to_xml_string(hue)
+        )
+        .unwrap();
+      }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+  }
+}
+
+/// Groups `samples` into one box per category and direction, in order of
+/// `categories`.
+fn box_plot<'a>(
+  samples: &[Sample<'a>],
+  categories: Vec<String>,
+  category: impl Fn(&Sample<'a>) -> String,
+  value: impl Fn(&Sample<'a>) -> f64,
+) -> BoxPlot {
+  let mut directions = samples
+    .iter()
+    .map(|sample| sample.direction)
+    .collect::<Vec<_>>();
+  directions.sort_by_key(|direction| *direction as u8);
+  directions.dedup();
+
+  let boxes = categories
+    .iter()
+    .map(|c| {
+      directions
+        .iter()
+        .map(|direction| {
+          BoxStats::compute(
+            samples
+              .iter()
+              .filter(|sample| sample.direction == *direction && &category(sample) == c)
+              .map(&value)
+              .collect(),
+          )
+        })
+        .collect()
+    })
+    .collect();
+
+  BoxPlot {
+    size: (5., 2.5),
+    x_label: String::new(),
+    y_label: "Slice size".into(),
+    categories,
+    hues: directions.iter().map(|d| format!("{d:?}")).collect(),
+    boxes,
+    show_fliers: true,
+    show_legend: true,
+    box_width: 0.5,
+  }
+}
+
+/// Renders the figures for the results of analysis configuration `config`, grouped
+/// by crate name, returning each figure's file name and plot:
+///
+/// * `linefrac.svg`: fraction of lines in the slice, by direction.
+/// * `linefrac-by-size.svg`: the same, split by whether the function has at least
+///   the mean number of lines.
+/// * `linefrac-by-size-crate.svg`: fraction of tokens in the slice, by crate and
+///   direction, with crates ordered by median.
+pub fn figures(
+  crates: &[(String, Vec<EvalResult>)],
+  config: &str,
+) -> Vec<(&'static str, BoxPlot)> {
+  let samples = crates
+    .iter()
+    .flat_map(|(crate_name, results)| {
+      results
+        .iter()
+        .filter(|result| result.config == config && result.num_lines > 0)
+        .map(move |result| Sample {
+          crate_name,
+          direction: result.direction,
+          num_lines: result.num_lines,
+          line_fraction: fraction(result.num_relevant_lines, result.num_lines),
+          token_fraction: fraction(result.num_relevant_tokens, result.num_tokens),
+        })
+    })
+    .collect::<Vec<_>>();
+  if samples.is_empty() {
+    return Vec::new();
+  }
+
+  // Directions are the categories rather than the hues here.
+  let mut by_direction = box_plot(
+    &samples,
+    vec![String::new()],
+    |_| String::new(),
+    |sample| sample.line_fraction,
+  );
+  by_direction.categories = by_direction.hues.clone();
+  by_direction.hues = vec![String::new()];
+  by_direction.boxes = by_direction
+    .boxes
+    .remove(0)
+    .into_iter()
+    .map(|b| vec![b])
+    .collect();
+  by_direction.size = (2.75, 2.5);
+  by_direction.x_label = "Direction".into();
+
+  let cutoff = (samples.iter().map(|s| s.num_lines).sum::<usize>() as f64
+    / samples.len() as f64)
+    .round() as usize;
+  let mut by_size = box_plot(
+    &samples,
+    vec!["No".into(), "Yes".into()],
+    |sample| {
+      if sample.num_lines >= cutoff {
+        "Yes"
+      } else {
+        "No"
+      }
+      .into()
+    },
+    |sample| sample.line_fraction,
+  );
+  by_size.x_label = format!("\u{2265} {cutoff} lines of code?");
+
+  let mut medians = crates
+    .iter()
+    .filter_map(|(crate_name, _)| {
+      let stats = BoxStats::compute(
+        samples
+          .iter()
+          .filter(|sample| sample.crate_name == crate_name)
+          .map(|sample| sample.token_fraction)
+          .collect(),
+      )?;
+      Some((crate_name.clone(), stats.median))
+    })
+    .collect::<Vec<_>>();
+  medians.sort_by(|a, b| a.1.total_cmp(&b.1));
+  let mut by_crate = box_plot(
+    &samples,
+    medians
+      .into_iter()
+      .map(|(crate_name, _)| crate_name)
+      .collect(),
+    |sample| sample.crate_name.to_string(),
+    |sample| sample.token_fraction,
+  );
+  by_crate.size = (8., 2.5);
+  by_crate.x_label = "Crate".into();
+  by_crate.show_fliers = false;
+  by_crate.show_legend = false;
+  by_crate.box_width = 0.6;
+
+  vec![
+    ("linefrac.svg", by_direction),
+    ("linefrac-by-size.svg", by_size),
+    ("linefrac-by-size-crate.svg", by_crate),
+  ]
+}
+
+/// Writes the [`figures`] into `dir`.
+pub fn write_figures(
+  crates: &[(String, Vec<EvalResult>)],
+  config: &str,
+  dir: impl AsRef<Path>,
+) -> Result<()> {
+  let dir = dir.as_ref();
+  fs::create_dir_all(dir)?;
+  for (file_name, plot) in figures(crates, config) {
+    fs::write(dir.join(file_name), plot.to_svg())?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::schema::{self, SCHEMA_VERSION};
+
+  fn quartiles(values: &[f64]) -> (f64, f64, f64) {
+    let stats = BoxStats::compute(values.to_vec()).unwrap();
+    (stats.q1, stats.median, stats.q3)
+  }
+
+  #[test]
+  fn no_box_without_values() {
+    assert!(BoxStats::compute(Vec::new()).is_none());
+  }
+
+  #[test]
+  fn single_value_collapses_the_box() {
+    let stats = BoxStats::compute(vec![0.3]).unwrap();
+    assert_eq!((stats.q1, stats.median, stats.q3), (0.3, 0.3, 0.3));
+    assert_eq!((stats.whisker_low, stats.whisker_high), (0.3, 0.3));
+    assert!(stats.fliers.is_empty());
+  }
+
+  #[test]
+  fn interpolates_quartiles_linearly() {
+    assert_eq!(quartiles(&[1., 2., 3., 4., 5.]), (2., 3., 4.));
+    assert_eq!(quartiles(&[4., 1., 3., 2.]), (1.75, 2.5, 3.25));
+  }
+
+  #[test]
+  fn whiskers_stop_at_the_last_value_inside() {
+    let stats = BoxStats::compute(vec![1., 2., 3., 4., 100.]).unwrap();
+    assert_eq!((stats.whisker_low, stats.whisker_high), (1., 4.));
+    assert_eq!(stats.fliers, vec![100.]);
+  }
+
+  #[test]
+  fn only_plots_one_config() {
+    let result: EvalResult =
+      serde_json::from_value(schema::tests::row(SCHEMA_VERSION)).unwrap();
+    assert_eq!(result.config, "recursive");
+    let crates = [("krate".to_string(), vec![result])];
+    assert!(figures(&crates, "modular").is_empty());
+    assert_eq!(figures(&crates, "recursive").len(), 3);
+  }
+}
@@ -0,0 +1,548 @@
+//! The dependence graph underlying a function's slices, for inspecting why a slice
+//! is large in Graphviz (DOT) or Gephi (GraphML).
+//!
+//! Nodes are the places the function's instructions read or write, labeled with
+//! their source: variables by name and temporaries by the expression they hold.
+//! Edges come from Flowistry's information flow results, so they account for
+//! aliasing and for conflicts between a place and its fields. A data edge runs to
+//! each written place from the places written by the instructions it directly
+//! depends on, skipping those whose influence already flows through another such
+//! instruction. Places the function never writes, like its arguments, get an edge
+//! from wherever they are read. A write through a reference is a node of its own,
+//! e.g. `*r`, with edges to the later reads of the places `r` may point to. A
+//! control edge runs from the places a branch switches on to each place written
+//! under that branch, if the write depends on the branch.
+
+use std::{
+  collections::{HashMap, HashSet},
+  fmt::Write,
+  fs,
+  hash::Hash,
+  path::Path,
+};
+
+use anyhow::{bail, Result};
+use either::Either;
+use flowistry::{
+  infoflow,
+  mir::{borrowck_facts, utils::BodyExt},
+  source_map::Range,
+};
+use rustc_middle::{
+  mir::{
+    visit::{PlaceContext, Visitor},
+    Body, Local, Location, Place, ProjectionElem, StatementKind, TerminatorKind,
+    VarDebugInfoContents,
+  },
+  ty::TyCtxt,
+};
+use rustc_span::{source_map::SourceMap, Symbol};
+use serde::Serialize;
+
+use crate::{bodies::CandidateBody, utils::to_xml_string};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+  pub id: usize,
+  /// The place in source syntax, e.g. `(*x).0`.
+  pub label: String,
+  /// The place in MIR syntax.
+  pub mir: String,
+  /// Source range of the variable or temporary the place belongs to.
+  pub range: Option<Range>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+  Data,
+  Control,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Edge {
+  pub from: usize,
+  pub to: usize,
+  pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependenceGraph {
+  pub function_path: String,
+  pub nodes: Vec<Node>,
+  pub edges: Vec<Edge>,
+}
+
+/// Collects every place used by a statement or terminator, except storage markers.
+#[derive(Default)]
+struct PlaceCollector<'tcx> {
+  places: Vec<Place<'tcx>>,
+}
+
+impl<'tcx> Visitor<'tcx> for PlaceCollector<'tcx> {
+  fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, _: Location) {
+    if context.is_use() {
+      self.places.push(*place);
+    }
+  }
+}
+
+/// The place written at `location` and the places it reads, or `None` if the
+/// instruction neither writes a place nor branches.
+fn accesses<'tcx>(
+  body: &Body<'tcx>,
+  location: Location,
+) -> Option<(Option<Place<'tcx>>, Vec<Place<'tcx>>)> {
+  let mut reads = PlaceCollector::default();
+  let written = match body.stmt_at(location) {
+    Either::Left(statement) => match &statement.kind {
+      StatementKind::Assign(box (place, rvalue)) => {
+        reads.visit_rvalue(rvalue, location);
+        Some(*place)
+      }
+      _ => return None,
+    },
+    Either::Right(terminator) => match &terminator.kind {
+      TerminatorKind::Call {
+        func,
+        args,
+        destination: Some((place, _)),
+        ..
+      } => {
+        reads.visit_operand(func, location);
+        for arg in args {
+          reads.visit_operand(arg, location);
+        }
+        Some(*place)
+      }
+      TerminatorKind::SwitchInt { discr, .. } => {
+        reads.visit_operand(discr, location);
+        None
+      }
+      _ => return None,
+    },
+  };
+  Some((written, reads.places))
+}
+
+/// The dependencies in `candidates` that are not reached through another candidate,
+/// given the transitive dependencies of each. Candidates that reach each other, e.g.
+/// around a loop, are all kept.
+fn direct_dependencies<T: Copy + Eq + Hash>(
+  candidates: &HashSet<T>,
+  transitive: &HashMap<T, HashSet<T>>,
+) -> Vec<T> {
+  candidates
+    .iter()
+    .copied()
+    .filter(|dependency| {
+      !candidates.iter().any(|other| {
+        other != dependency
+          && transitive[other].contains(dependency)
+          && !transitive[dependency].contains(other)
+      })
+    })
+    .collect()
+}
+
+fn to_dot_string(s: &str) -> String {
+  s.replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+/// Renders `place` like the source expression it stands for, starting from the name
+/// of its local.
+fn place_label(place: Place<'_>, local_name: &str) -> String {
+  let mut label = local_name.to_string();
+  for elem in place.projection {
+    label = match elem {
+      ProjectionElem::Deref => format!("*{label}"),
+      ProjectionElem::Field(field, _) if label.starts_with('*') => {
+        format!("({label}).{}", field.index())
+      }
+      ProjectionElem::Field(field, _) => format!("{label}.{}", field.index()),
+      ProjectionElem::Index(_) | ProjectionElem::ConstantIndex { .. } => {
+        format!("{label}[_]")
+      }
+      ProjectionElem::Subslice { .. } => format!("{label}[..]"),
+      ProjectionElem::Downcast(Some(variant), _) => format!("({label} as {variant})"),
+      ProjectionElem::Downcast(None, _) => label,
+    };
+  }
+  label
+}
+
+/// The name of each local: user variables by their name, temporaries by the source
+/// of the expression they hold, or else in MIR syntax.
+fn local_names(body: &Body<'_>, source_map: &SourceMap) -> HashMap<Local, String> {
+  let variables = body
+    .var_debug_info
+    .iter()
+    .filter_map(|info| match info.value {
+      VarDebugInfoContents::Place(place) => Some((place.as_local()?, info.name)),
+      VarDebugInfoContents::Const(_) => None,
+    })
+    .collect::<HashMap<Local, Symbol>>();
+
+  body
+    .local_decls
+    .indices()
+    .map(|local| {
+      let name = match variables.get(&local) {
+        Some(name) => name.to_string(),
+        None => source_map
+          .span_to_snippet(body.local_decls[local].source_info.span.source_callsite())
+          .ok()
+          .filter(|snippet| !snippet.is_empty() && !snippet.contains('\n'))
+          .unwrap_or_else(|| format!("{local:?}")),
+      };
+      (local, name)
+    })
+    .collect()
+}
+
+impl DependenceGraph {
+  pub fn build(tcx: TyCtxt<'_>, candidate: &CandidateBody) -> Self {
+    let local_def_id = tcx.hir().body_owner_def_id(candidate.body_id);
+    let body_with_facts = borrowck_facts::get_body_with_borrowck_facts(tcx, local_def_id);
+    let body = &body_with_facts.body;
+    let results = infoflow::compute_flow(tcx, candidate.body_id, body_with_facts);
+    let source_map = tcx.sess.source_map();
+
+    let instructions = body
+      .all_locations()
+      .filter_map(|location| Some((location, accesses(body, location)?)))
+      .collect::<HashMap<_, _>>();
+    let mut locations = instructions.keys().copied().collect::<Vec<_>>();
+    locations.sort();
+
+    // Places are numbered in order of first appearance.
+    let mut places = Vec::new();
+    let mut ids = HashMap::new();
+    for location in &locations {
+      let (written, reads) = &instructions[location];
+      for place in reads.iter().chain(written) {
+        ids.entry(*place).or_insert_with(|| {
+          places.push(*place);
+          places.len() - 1
+        });
+      }
+    }
+
+    let local_names = local_names(body, source_map);
+    let nodes = places
+      .iter()
+      .enumerate()
+      .map(|(id, place)| {
+        let span = body.local_decls[place.local]
+          .source_info
+          .span
+          .source_callsite();
+        Node {
+          id,
+          label: place_label(*place, &local_names[&place.local]),
+          mir: format!("{place:?}"),
+          range: Range::from_span(span, source_map).ok(),
+        }
+      })
+      .collect();
+
+    let written_at = |location: &Location| match instructions.get(location) {
+      Some((Some(written), _)) => Some(*written),
+      _ => None,
+    };
+    let written_places = locations
+      .iter()
+      .filter_map(written_at)
+      .collect::<HashSet<_>>();
+    let transitive = locations
+      .iter()
+      .filter_map(|location| {
+        let written = written_at(location)?;
+        let dependencies = results.state_at(*location).row(written).copied().collect();
+        Some((*location, dependencies))
+      })
+      .collect::<HashMap<_, HashSet<_>>>();
+
+    let mut edges = Vec::new();
+    for location in &locations {
+      let (written, reads) = &instructions[location];
+      let written = match written {
+        Some(written) => *written,
+        None => continue,
+      };
+
+      // Rows hold every instruction a place transitively depends on, including
+      // writes to its aliases and to overlapping places.
+      let state = results.state_at(*location);
+      let candidates = reads
+        .iter()
+        .flat_map(|read| state.row(*read).copied())
+        .filter(|dependency| {
+          dependency != location && transitive.contains_key(dependency)
+        })
+        .collect::<HashSet<_>>();
+      edges.extend(
+        direct_dependencies(&candidates, &transitive)
+          .into_iter()
+          .map(|dependency| Edge {
+            from: ids[&written_at(&dependency).unwrap()],
+            to: ids[&written],
+            kind: EdgeKind::Data,
+          }),
+      );
+      edges.extend(
+        reads
+          .iter()
+          .filter(|read| !written_places.contains(read))
+          .map(|read| Edge {
+            from: ids[read],
+            to: ids[&written],
+            kind: EdgeKind::Data,
+          }),
+      );
+
+      // Flowistry folds control dependencies into the same matrix, so only the
+      // branches the written place actually depends on are kept.
+      if let Some(branches) = results
+        .analysis
+        .control_dependencies
+        .dependent_on(location.block)
+      {
+        for branch in branches.iter() {
+          let branch = body.terminator_loc(branch);
+          if !state.row(written).any(|dependency| *dependency == branch) {
+            continue;
+          }
+          if let Some((None, discriminants)) = instructions.get(&branch) {
+            edges.extend(discriminants.iter().map(|discriminant| Edge {
+              from: ids[discriminant],
+              to: ids[&written],
+              kind: EdgeKind::Control,
+            }));
+          }
+        }
+      }
+    }
+
+    // Several instructions can write one place from the same places.
+    edges.sort_by_key(|edge| (edge.from, edge.to, edge.kind == EdgeKind::Control));
+    edges.dedup();
+
+    DependenceGraph {
+      function_path: candidate.function_path.clone(),
+      nodes,
+      edges,
+    }
+  }
+
+  pub fn to_dot(&self) -> String {
+    let mut dot = format!(
+      "digraph \"{}\" {{\n  node [shape=box, fontname=monospace];\n",
+      to_dot_string(&self.function_path)
+    );
+    for node in &self.nodes {
+      writeln!(
+        dot,
+        "  n{} [label=\"{}\", tooltip=\"{}\"];",
+        node.id,
+        to_dot_string(&node.label),
+        to_dot_string(&node.mir)
+      )
+      .unwrap();
+    }
+    for edge in &self.edges {
+      let style = match edge.kind {
+        EdgeKind::Data => "solid",
+        EdgeKind::Control => "dashed",
+      };
+      writeln!(dot, "  n{} -> n{} [style={style}];", edge.from, edge.to).unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+  }
+
+  pub fn to_graphml(&self) -> String {
+    let mut xml = String::from(concat!(
+      "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+      "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+      "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+      "  <key id=\"mir\" for=\"node\" attr.name=\"mir\" attr.type=\"string\"/>\n",
+      "  <key id=\"filename\" for=\"node\" attr.name=\"filename\" attr.type=\"string\"/>\n",
+      "  <key id=\"start\" for=\"node\" attr.name=\"start\" attr.type=\"int\"/>\n",
+      "  <key id=\"end\" for=\"node\" attr.name=\"end\" attr.type=\"int\"/>\n",
+      "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+    ));
+    writeln!(
+      xml,
+      "  <graph id=\"{}\" edgedefault=\"directed\">",
+      to_xml_string(&self.function_path)
+    )
+    .unwrap();
+    for node in &self.nodes {
+      writeln!(xml, "    <node id=\"n{}\">", node.id).unwrap();
+      writeln!(
+        xml,
+        "      <data key=\"label\">{}</data>",
+        to_xml_string(&node.label)
+      )
+      .unwrap();
+      writeln!(
+        xml,
+        "      <data key=\"mir\">{}</data>",
+        to_xml_string(&node.mir)
+      )
+      .unwrap();
+      if let Some(range) = &node.range {
+        writeln!(
+          xml,
+          "      <data key=\"filename\">{}</data>",
+          to_xml_string(&range.filename)
+        )
+        .unwrap();
+        writeln!(xml, "      <data key=\"start\">{}</data>", range.start).unwrap();
+        writeln!(xml, "      <data key=\"end\">{}</data>", range.end).unwrap();
+      }
+      xml.push_str("    </node>\n");
+    }
+    for (i, edge) in self.edges.iter().enumerate() {
+      let kind = match edge.kind {
+        EdgeKind::Data => "data",
+        EdgeKind::Control => "control",
+      };
+      writeln!(
+        xml,
+        "    <edge id=\"e{i}\" source=\"n{}\" target=\"n{}\">",
+        edge.from, edge.to
+      )
+      .unwrap();
+      writeln!(xml, "      <data key=\"kind\">{kind}</data>").unwrap();
+      xml.push_str("    </edge>\n");
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+  }
+
+  /// Writes the graph in the format given by the extension of `path`: `.dot`,
+  /// `.graphml` or `.json`.
+  pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let contents = match path.extension().and_then(|ext| ext.to_str()) {
+      Some("dot" | "gv") => self.to_dot(),
+      Some("graphml") => self.to_graphml(),
+      Some("json") => serde_json::to_string(self)?,
+      _ => bail!("unknown graph format for {}", path.display()),
+    };
+    fs::write(path, contents)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn graph() -> DependenceGraph {
+    DependenceGraph {
+      function_path: "krate::<Vec<T>>::f".into(),
+      nodes: vec![
+        Node {
+          id: 0,
+          label: "x".into(),
+          mir: "_1".into(),
+          range: Some(Range {
+            start: 4,
+            end: 5,
+            filename: "lib.rs".into(),
+          }),
+        },
+        Node {
+          id: 1,
+          label: "a < \"b\"".into(),
+          mir: "_2".into(),
+          range: None,
+        },
+      ],
+      edges: vec![
+        Edge {
+          from: 0,
+          to: 1,
+          kind: EdgeKind::Data,
+        },
+        Edge {
+          from: 1,
+          to: 1,
+          kind: EdgeKind::Control,
+        },
+      ],
+    }
+  }
+
+  #[test]
+  fn keeps_only_direct_dependencies() {
+    // 2 depends on 1, which depends on 0; 3 and 4 depend on each other.
+    let transitive = HashMap::from([
+      (0, HashSet::from([0])),
+      (1, HashSet::from([0, 1])),
+      (2, HashSet::from([0, 1, 2])),
+      (3, HashSet::from([3, 4])),
+      (4, HashSet::from([3, 4])),
+    ]);
+    let mut direct = direct_dependencies(&HashSet::from([0, 1, 2]), &transitive);
+    direct.sort_unstable();
+    assert_eq!(direct, [2]);
+
+    let mut direct = direct_dependencies(&HashSet::from([0, 3, 4]), &transitive);
+    direct.sort_unstable();
+    assert_eq!(direct, [0, 3, 4]);
+  }
+
+  #[test]
+  fn escapes_dot_strings() {
+    assert_eq!(to_dot_string("a \"b\"\\\nc"), "a \\\"b\\\"\\\\\\nc");
+  }
+
+  #[test]
+  fn writes_dot() {
+    assert_eq!(
+      graph().to_dot(),
+      concat!(
+        "digraph \"krate::<Vec<T>>::f\" {\n",
+        "  node [shape=box, fontname=monospace];\n",
+        "  n0 [label=\"x\", tooltip=\"_1\"];\n",
+        "  n1 [label=\"a < \\\"b\\\"\", tooltip=\"_2\"];\n",
+        "  n0 -> n1 [style=solid];\n",
+        "  n1 -> n1 [style=dashed];\n",
+        "}\n",
+      )
+    );
+  }
+
+  #[test]
+  fn writes_graphml() {
+    let xml = graph().to_graphml();
+    assert!(xml.contains(
+      "<graph id=\"krate::&lt;Vec&lt;T&gt;&gt;::f\" edgedefault=\"directed\">"
+    ));
+    assert!(xml.contains(concat!(
+      "    <node id=\"n0\">\n",
+      "      <data key=\"label\">x</data>\n",
+      "      <data key=\"mir\">_1</data>\n",
+      "      <data key=\"filename\">lib.rs</data>\n",
+      "      <data key=\"start\">4</data>\n",
+      "      <data key=\"end\">5</data>\n",
+      "    </node>\n",
+      "    <node id=\"n1\">\n",
+      "      <data key=\"label\">a &lt; &quot;b&quot;</data>\n",
+      "      <data key=\"mir\">_2</data>\n",
+      "    </node>\n",
+    )));
+    assert!(xml.contains(concat!(
+      "    <edge id=\"e1\" source=\"n1\" target=\"n1\">\n",
+      "      <data key=\"kind\">control</data>\n",
+      "    </edge>\n",
+      "  </graph>\n</graphml>\n",
+    )));
+  }
+}
@@ -0,0 +1,173 @@
+//! Per-function time series built from eval results of successive revisions of a
+//! crate, joined on [stable IDs](crate::stable_id).
+
+use std::collections::HashMap;
+
+use flowistry::infoflow::Direction;
+use serde::{Deserialize, Serialize};
+
+use crate::{schema::EvalResult, utils::fraction};
+
+/// Slice sizes of one function at one revision, for one configuration and direction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionSnapshot {
+  pub revision: String,
+  /// Position of the revision in the evaluated sequence.
+  pub revision_index: usize,
+  pub stable_id: String,
+  pub content_hash: Option<String>,
+  pub config: String,
+  pub direction: Direction,
+  pub num_tokens: usize,
+  pub num_lines: usize,
+  pub num_criteria: usize,
+  pub mean_relevant_token_fraction: f64,
+  pub max_relevant_token_fraction: f64,
+  pub mean_relevant_line_fraction: f64,
+  /// Whether the function's source changed since the last revision it appeared in,
+  /// or `None` if this is its first appearance.
+  pub changed: Option<bool>,
+}
+
+/// Aggregates the per-criterion results of one revision into one snapshot per
+/// function, configuration and direction.
+pub fn summarize_revision(
+  revision: &str,
+  revision_index: usize,
+  results: &[EvalResult],
+) -> Vec<FunctionSnapshot> {
+  let mut groups: HashMap<(&str, &str, u8), Vec<&EvalResult>> = HashMap::new();
+  for result in results {
+    // Rows written before stable IDs existed fall back to the unstable path.
+    let id = result.stable_id.as_deref().unwrap_or(&result.function_path);
+    groups
+      .entry((id, &result.config, result.direction as u8))
+      .or_default()
+      .push(result);
+  }
+
+  let mut snapshots = groups
+    .into_iter()
+    .map(|((stable_id, config, _), rows)| {
+      let first = rows[0];
+      let token_fractions = rows
+        .iter()
+        .map(|row| fraction(row.num_relevant_tokens, row.num_tokens))
+        .collect::<Vec<_>>();
+      let line_fractions = rows
+        .iter()
+        .map(|row| fraction(row.num_relevant_lines, row.num_lines));
+      let n = rows.len() as f64;
+
+      FunctionSnapshot {
+        revision: revision.to_string(),
+        revision_index,
+        stable_id: stable_id.to_string(),
+        content_hash: first.content_hash.clone(),
+        config: config.to_string(),
+        direction: first.direction,
+        num_tokens: first.num_tokens,
+        num_lines: first.num_lines,
+        num_criteria: rows.len(),
+        mean_relevant_token_fraction: token_fractions.iter().sum::<f64>() / n,
+        max_relevant_token_fraction: token_fractions.iter().copied().fold(0., f64::max),
+        mean_relevant_line_fraction: line_fractions.sum::<f64>() / n,
+        changed: None,
+      }
+    })
+    .collect::<Vec<_>>();
+
+  snapshots.sort_by(|a, b| {
+    (&a.stable_id, &a.config, a.direction as u8).cmp(&(
+      &b.stable_id,
+      &b.config,
+      b.direction as u8,
+    ))
+  });
+  snapshots
+}
+
+/// Fills in [`FunctionSnapshot::changed`] by comparing each snapshot's content hash
+/// with the function's previous snapshot. `snapshots` must be ordered by revision.
+pub fn mark_changes(snapshots: &mut [FunctionSnapshot]) {
+  let mut last_hash: HashMap<(String, String, u8), Option<String>> = HashMap::new();
+  for snapshot in snapshots {
+    let key = (
+      snapshot.stable_id.clone(),
+      snapshot.config.clone(),
+      snapshot.direction as u8,
+    );
+    snapshot.changed = last_hash
+      .insert(key, snapshot.content_hash.clone())
+      .map(|previous| previous != snapshot.content_hash);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn snapshot(
+    revision_index: usize,
+    stable_id: &str,
+    direction: Direction,
+    content_hash: Option<&str>,
+  ) -> FunctionSnapshot {
+    FunctionSnapshot {
+      revision: format!("v{revision_index}"),
+      revision_index,
+      stable_id: stable_id.into(),
+      content_hash: content_hash.map(str::to_string),
+      config: "modular".into(),
+      direction,
+      num_tokens: 10,
+      num_lines: 2,
+      num_criteria: 1,
+      mean_relevant_token_fraction: 0.5,
+      max_relevant_token_fraction: 0.5,
+      mean_relevant_line_fraction: 0.5,
+      changed: None,
+    }
+  }
+
+  fn changes(snapshots: &mut [FunctionSnapshot]) -> Vec<Option<bool>> {
+    mark_changes(snapshots);
+    snapshots.iter().map(|snapshot| snapshot.changed).collect()
+  }
+
+  #[test]
+  fn marks_changes_since_the_last_appearance() {
+    let mut snapshots = vec![
+      snapshot(0, "krate::f", Direction::Forward, Some("a")),
+      snapshot(1, "krate::f", Direction::Forward, Some("a")),
+      snapshot(2, "krate::g", Direction::Forward, Some("c")),
+      snapshot(3, "krate::f", Direction::Forward, Some("b")),
+    ];
+    assert_eq!(changes(&mut snapshots), vec![
+      None,
+      Some(false),
+      None,
+      Some(true)
+    ]);
+  }
+
+  #[test]
+  fn tracks_directions_separately() {
+    let mut snapshots = vec![
+      snapshot(0, "krate::f", Direction::Forward, Some("a")),
+      snapshot(1, "krate::f", Direction::Backward, Some("b")),
+      snapshot(1, "krate::f", Direction::Forward, Some("b")),
+    ];
+    assert_eq!(changes(&mut snapshots), vec![None, None, Some(true)]);
+  }
+
+  #[test]
+  fn missing_hashes_only_match_missing_hashes() {
+    let mut snapshots = vec![
+      snapshot(0, "krate::f", Direction::Forward, None),
+      snapshot(1, "krate::f", Direction::Forward, None),
+      snapshot(2, "krate::f", Direction::Forward, Some("a")),
+    ];
+    assert_eq!(changes(&mut snapshots), vec![None, Some(false), Some(true)]);
+  }
+}
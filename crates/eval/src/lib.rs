@@ -13,17 +13,53 @@ extern crate rustc_parse;
 extern crate rustc_serialize;
 extern crate rustc_span;
 
+pub mod annotations;
+mod baseline;
+pub mod bodies;
+mod config;
+pub mod determinism;
+pub mod export;
+pub mod fault_benchmark;
+pub mod fault_localization;
+mod features;
+pub mod figures;
+pub mod graph;
+pub mod history;
+pub mod overlap;
+pub mod progress;
+pub mod query;
+pub mod rollup;
+pub mod schema;
+mod server;
+mod source;
+pub mod stable_id;
+mod statements;
+pub mod trace;
+mod utils;
 mod visitor;
 
-use std::{env, fs};
+use std::{env, fs, path::PathBuf, time::Instant};
 
 use flowistry::mir::borrowck_facts;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::LOCAL_CRATE;
+use serde::Serialize;
 
-struct Callbacks {
-  output_path: String,
-}
+pub use crate::{
+  bodies::{collect_bodies, CandidateBody},
+  config::AnalysisConfig,
+  progress::Progress,
+  query::QueryTarget,
+  schema::EvalResult,
+  source::{eval_source, SOURCE_FILE_NAME},
+  trace::Trace,
+};
+
+/// Compiler callbacks that run a function on the crate's type context once it is
+/// parsed, and then stop.
+pub(crate) struct TcxCallbacks<F>(pub F);
 
-impl rustc_driver::Callbacks for Callbacks {
+impl<F: FnMut(TyCtxt<'_>) + Send> rustc_driver::Callbacks for TcxCallbacks<F> {
   fn config(&mut self, config: &mut rustc_interface::Config) {
     // You MUST configure rustc to ensure `get_body_with_borrowck_facts` will work.
     config.override_queries = Some(borrowck_facts::override_queries);
@@ -34,25 +70,214 @@ impl rustc_driver::Callbacks for Callbacks {
     _compiler: &rustc_interface::interface::Compiler,
     queries: &'tcx rustc_interface::Queries<'tcx>,
   ) -> rustc_driver::Compilation {
-    queries.global_ctxt().unwrap().take().enter(|tcx| {
-      let mut counter = visitor::ItemCounter { count: 0 };
-      visitor::visit_bodies(tcx, &mut counter);
+    queries
+      .global_ctxt()
+      .unwrap()
+      .take()
+      .enter(|tcx| (self.0)(tcx));
+    rustc_driver::Compilation::Stop
+  }
+}
 
-      let mut eval_visitor = visitor::EvalCrateVisitor::new(counter.count);
-      visitor::visit_bodies(tcx, &mut eval_visitor);
+fn run_compiler(
+  args: &[String],
+  f: impl FnMut(TyCtxt<'_>) + Send,
+) -> rustc_interface::interface::Result<()> {
+  rustc_driver::RunCompiler::new(args, &mut TcxCallbacks(f)).run()
+}
 
-      let json = serde_json::to_string(&eval_visitor.eval_results).unwrap();
+struct Eval {
+  output_path: String,
+  export_paths: Vec<String>,
+  configs: Vec<AnalysisConfig>,
+  compare_recursive: bool,
+  determinism_path: Option<String>,
+  slices_path: Option<String>,
+  overlap_path: Option<String>,
+  overlap_threshold: f64,
+  progress_path: Option<String>,
+  trace_path: Option<String>,
+  annotations: Vec<annotations::Annotation>,
+  only_run: Option<String>,
+  sample: Option<usize>,
+  sample_seed: u64,
+}
 
-      fs::write(&self.output_path, &json).unwrap();
-    });
+impl Eval {
+  fn run(&self, tcx: TyCtxt<'_>) {
+    let mut trace = Trace::new(self.trace_path.is_some());
+    let start = Instant::now();
+    let mut bodies = bodies::collect_bodies(tcx);
+    trace.complete("crate", "collect_bodies", start, None);
+    let num_bodies = bodies.len();
+    if let Some(spec) = &self.only_run {
+      bodies = bodies::filter_only_run(bodies, spec);
+    }
+    if let Some(n) = self.sample {
+      bodies = bodies::sample(bodies, n, self.sample_seed);
+    }
 
-    rustc_driver::Compilation::Stop
+    let options = visitor::EvalOptions {
+      configs: self.configs.clone(),
+      compare_recursive: self.compare_recursive,
+      verify_determinism: self.determinism_path.is_some(),
+      record_slices: self.slices_path.is_some(),
+      overlap_threshold: self.overlap_path.as_ref().map(|_| self.overlap_threshold),
+      annotations: self.annotations.clone(),
+    };
+    let progress = Progress::new(
+      tcx.crate_name(LOCAL_CRATE).to_string(),
+      bodies.len(),
+      self.progress_path.as_ref().map(PathBuf::from),
+    );
+    let mut eval_visitor =
+      visitor::EvalCrateVisitor::new(progress, trace, num_bodies, options);
+    for body in &bodies {
+      eval_visitor.analyze(tcx, body);
+    }
+    eval_visitor.progress.finish();
+
+    let start = Instant::now();
+    write_json(&self.output_path, &eval_visitor.eval_results);
+
+    for path in &self.export_paths {
+      export::write_results(path, &eval_visitor.eval_results).unwrap();
+    }
+
+    if let Some(path) = &self.determinism_path {
+      write_json(path, &eval_visitor.nondeterminism);
+    }
+
+    if let Some(path) = &self.slices_path {
+      write_json(path, &eval_visitor.recorded_slices);
+    }
+
+    if let Some(path) = &self.overlap_path {
+      write_json(path, &eval_visitor.slice_overlaps);
+    }
+
+    if let Some(path) = &self.trace_path {
+      let trace = &mut eval_visitor.trace;
+      trace.complete("crate", "write results", start, None);
+      trace.write(path).unwrap();
+    }
+  }
+}
+
+/// Prints the slices of `target`, or reports a compiler error so that the driver
+/// exits with a failure.
+fn query(tcx: TyCtxt<'_>, target: &QueryTarget) {
+  match query::query(tcx, target) {
+    Ok(output) => println!("{output}"),
+    Err(e) => {
+      tcx.sess.err(format!("query failed: {e:?}"));
+    }
   }
 }
 
+fn write_dependence_graph(tcx: TyCtxt<'_>, function: &str, paths: &[String]) {
+  let bodies = bodies::filter_only_run(bodies::collect_bodies(tcx), function);
+  match &bodies[..] {
+    [body] => {
+      let graph = graph::DependenceGraph::build(tcx, body);
+      for path in paths {
+        graph.write(path).unwrap();
+      }
+    }
+    _ => eprintln!(
+      "DEPENDENCE_GRAPH matched {} functions, expected 1",
+      bodies.len()
+    ),
+  }
+}
+
+fn write_json(path: &str, value: &impl Serialize) {
+  let json = serde_json::to_string(value).unwrap();
+  fs::write(path, &json).unwrap();
+}
+
 pub fn run(args: &[String]) -> rustc_interface::interface::Result<()> {
-  let mut callbacks = Callbacks {
+  // SERVE=1 answers JSON-RPC requests on stdin until shutdown, see `server`.
+  if env::var("SERVE").is_ok() {
+    let configs = AnalysisConfig::from_env().unwrap();
+    return run_compiler(args, |tcx| {
+      let server = server::Server::new(tcx, configs.clone());
+      if let Err(e) = server.serve() {
+        eprintln!("Server failed: {e:?}");
+      }
+    });
+  }
+
+  // QUERY=file:line:column prints the slices of one criterion instead of
+  // evaluating the whole crate.
+  if let Ok(target) = env::var("QUERY") {
+    let target = target.parse().unwrap();
+    return run_compiler(args, |tcx| query(tcx, &target));
+  }
+
+  // DEPENDENCE_GRAPH=<function> writes the dependence graph of one function, selected
+  // like ONLY_RUN, to each of the comma-separated GRAPH_PATHS.
+  if let Ok(function) = env::var("DEPENDENCE_GRAPH") {
+    let paths = env::var("GRAPH_PATHS")
+      .unwrap()
+      .split(',')
+      .map(str::to_string)
+      .collect::<Vec<_>>();
+    return run_compiler(args, |tcx| write_dependence_graph(tcx, &function, &paths));
+  }
+
+  // FAULT_TASKS=tasks.json checks whether slices from each task's criteria reach
+  // its seeded bug, instead of evaluating the whole crate.
+  if let Ok(path) = env::var("FAULT_TASKS") {
+    let tasks: Vec<fault_localization::FaultTask> = utils::read_json(path).unwrap();
+    let output_path = env::var("OUTPUT_PATH").unwrap();
+    return run_compiler(args, |tcx| {
+      write_json(&output_path, &fault_localization::localize(tcx, &tasks));
+    });
+  }
+
+  // FAULT_BENCHMARKS=benchmarks.json slices backward from the assertions of failing
+  // tests and scores how soon each benchmark's bug is reached.
+  if let Ok(path) = env::var("FAULT_BENCHMARKS") {
+    let benchmarks: Vec<fault_benchmark::FaultBenchmark> =
+      utils::read_json(path).unwrap();
+    let output_path = env::var("OUTPUT_PATH").unwrap();
+    // #[test] functions are only compiled in test mode.
+    let mut args = args.to_vec();
+    if !args.iter().any(|arg| arg == "--test") {
+      args.push("--test".into());
+    }
+    return run_compiler(&args, |tcx| {
+      write_json(
+        &output_path,
+        &fault_benchmark::run_benchmarks(tcx, &benchmarks),
+      );
+    });
+  }
+
+  let eval = Eval {
     output_path: env::var("OUTPUT_PATH").unwrap(),
+    export_paths: env::var("EXPORT_PATHS")
+      .map(|paths| paths.split(',').map(str::to_string).collect())
+      .unwrap_or_default(),
+    configs: AnalysisConfig::from_env().unwrap(),
+    compare_recursive: env::var("COMPARE_RECURSIVE").is_ok(),
+    determinism_path: env::var("VERIFY_DETERMINISM").ok(),
+    slices_path: env::var("SLICES_PATH").ok(),
+    overlap_path: env::var("OVERLAP_PATH").ok(),
+    overlap_threshold: env::var("OVERLAP_THRESHOLD")
+      .map(|threshold| threshold.parse().unwrap())
+      .unwrap_or(0.9),
+    progress_path: env::var("PROGRESS_PATH").ok(),
+    trace_path: env::var("TRACE_PATH").ok(),
+    annotations: env::var("ANNOTATIONS_PATH")
+      .map(|path| utils::read_json(path).unwrap())
+      .unwrap_or_default(),
+    only_run: env::var("ONLY_RUN").ok(),
+    sample: env::var("SAMPLE").ok().map(|n| n.parse().unwrap()),
+    sample_seed: env::var("SAMPLE_SEED")
+      .map(|seed| seed.parse().unwrap())
+      .unwrap_or(0),
   };
-  rustc_driver::RunCompiler::new(args, &mut callbacks).run()
+  run_compiler(args, |tcx| eval.run(tcx))
 }
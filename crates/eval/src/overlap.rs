@@ -0,0 +1,99 @@
+use flowistry::{infoflow::Direction, source_map::Range};
+use rustc_data_structures::fx::FxHashSet as HashSet;
+use serde::Serialize;
+
+/// How much the slices of different places in one function overlap, measured over
+/// the sets of tokens each slice covers.
+#[derive(Debug, Serialize)]
+pub struct SliceOverlap {
+  pub function_path: String,
+  pub config: String,
+  pub direction: Direction,
+  pub num_places: usize,
+  /// Number of slices that are not token-for-token identical to another.
+  pub num_distinct_slices: usize,
+  /// Number of clusters in the partition of places where two places are in the same
+  /// cluster if their slices have a Jaccard index of at least `threshold`, directly
+  /// or through other members. A place similar to no other place is a cluster of
+  /// its own, so this equals `num_places` when no two slices are similar.
+  pub num_clusters: usize,
+  pub threshold: f64,
+  pub mean_jaccard: f64,
+  pub clusters: Vec<Vec<Range>>,
+}
+
+pub fn jaccard(a: &HashSet<usize>, b: &HashSet<usize>) -> f64 {
+  let union = a.union(b).count();
+  if union == 0 {
+    return 1.;
+  }
+  a.intersection(b).count() as f64 / union as f64
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+  if parents[i] != i {
+    parents[i] = find(parents, parents[i]);
+  }
+  parents[i]
+}
+
+impl SliceOverlap {
+  pub fn compute(
+    function_path: &str,
+    config: &str,
+    direction: Direction,
+    slices: &[(Range, HashSet<usize>)],
+    threshold: f64,
+  ) -> Self {
+    let n = slices.len();
+    let mut parents = (0 .. n).collect::<Vec<_>>();
+    let mut total_jaccard = 0.;
+    for i in 0 .. n {
+      for j in i + 1 .. n {
+        let similarity = jaccard(&slices[i].1, &slices[j].1);
+        total_jaccard += similarity;
+        if similarity >= threshold {
+          let (root_i, root_j) = (find(&mut parents, i), find(&mut parents, j));
+          parents[root_j] = root_i;
+        }
+      }
+    }
+
+    let num_pairs = n * n.saturating_sub(1) / 2;
+    let mean_jaccard = if num_pairs > 0 {
+      total_jaccard / num_pairs as f64
+    } else {
+      1.
+    };
+
+    let mut clusters: Vec<(usize, Vec<Range>)> = Vec::new();
+    for (i, (range, _)) in slices.iter().enumerate() {
+      let root = find(&mut parents, i);
+      let range = range.clone();
+      match clusters.iter_mut().find(|(other, _)| *other == root) {
+        Some((_, members)) => members.push(range),
+        None => clusters.push((root, vec![range])),
+      }
+    }
+
+    let num_distinct_slices = (0 .. n)
+      .filter(|i| {
+        !slices[.. *i]
+          .iter()
+          .any(|(_, other)| other == &slices[*i].1)
+      })
+      .count();
+
+    SliceOverlap {
+      function_path: function_path.to_string(),
+      config: config.to_string(),
+      direction,
+      num_places: n,
+      num_distinct_slices,
+      num_clusters: clusters.len(),
+      threshold,
+      mean_jaccard,
+      clusters: clusters.into_iter().map(|(_, members)| members).collect(),
+    }
+  }
+}
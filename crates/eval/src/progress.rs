@@ -0,0 +1,168 @@
+use std::{
+  collections::VecDeque,
+  fs,
+  io::Write,
+  path::PathBuf,
+  time::{Duration, Instant},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// Number of recent functions used to estimate the time per remaining function.
+const WINDOW: usize = 50;
+
+/// Minimum time between two writes of the progress file.
+const WRITE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A snapshot of the driver's progress, as written to `PROGRESS_PATH`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProgressSnapshot {
+  pub crate_name: String,
+  pub total: usize,
+  pub done: usize,
+  pub failed: usize,
+  pub elapsed: f64,
+  pub eta: Option<f64>,
+  pub current_function: Option<String>,
+  pub finished: bool,
+}
+
+/// Tracks how many functions the eval driver has analyzed, shows a live status line
+/// on stderr when it is a terminal, and periodically writes a [`ProgressSnapshot`].
+pub struct Progress {
+  crate_name: String,
+  total: usize,
+  done: usize,
+  failed: usize,
+  start: Instant,
+  current: Option<(String, Instant)>,
+  recent: VecDeque<f64>,
+  path: Option<PathBuf>,
+  last_write: Option<Instant>,
+  live: bool,
+}
+
+fn format_duration(secs: f64) -> String {
+  let secs = secs as u64;
+  format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl Progress {
+  pub fn new(crate_name: String, total: usize, path: Option<PathBuf>) -> Self {
+    Progress {
+      crate_name,
+      total,
+      done: 0,
+      failed: 0,
+      start: Instant::now(),
+      current: None,
+      recent: VecDeque::with_capacity(WINDOW),
+      path,
+      last_write: None,
+      live: atty::is(atty::Stream::Stderr),
+    }
+  }
+
+  pub fn total(&self) -> usize {
+    self.total
+  }
+
+  /// Estimated seconds until all functions are analyzed, based on the mean time of
+  /// the last [`WINDOW`] functions.
+  pub fn eta(&self) -> Option<f64> {
+    if self.recent.is_empty() {
+      return None;
+    }
+    let mean = self.recent.iter().sum::<f64>() / self.recent.len() as f64;
+    let remaining = self.total.saturating_sub(self.done + self.failed);
+    Some(mean * remaining as f64)
+  }
+
+  pub fn start_function(&mut self, function_path: &str) {
+    self.current = Some((function_path.to_string(), Instant::now()));
+    self.report(false);
+  }
+
+  pub fn finish_function(&mut self, succeeded: bool) {
+    if let Some((_, start)) = self.current.take() {
+      if self.recent.len() == WINDOW {
+        self.recent.pop_front();
+      }
+      self.recent.push_back(start.elapsed().as_secs_f64());
+    }
+
+    if succeeded {
+      self.done += 1;
+    } else {
+      self.failed += 1;
+    }
+    self.report(false);
+  }
+
+  pub fn finish(&mut self) {
+    self.current = None;
+    self.report(true);
+    if self.live {
+      eprintln!();
+    }
+    info!(
+      "Finished {} functions ({} failed) in {}",
+      self.done + self.failed,
+      self.failed,
+      format_duration(self.start.elapsed().as_secs_f64())
+    );
+  }
+
+  fn report(&mut self, finished: bool) {
+    let elapsed = self.start.elapsed().as_secs_f64();
+    let eta = self.eta();
+    let current_function = self.current.as_ref().map(|(path, _)| path.as_str());
+
+    if self.live {
+      let eta = eta.map(format_duration).unwrap_or_else(|| "?".into());
+      eprint!(
+        "\r\x1b[K[{}] {}/{} done, {} failed, elapsed {}, ETA {} {}",
+        self.crate_name,
+        self.done,
+        self.total,
+        self.failed,
+        format_duration(elapsed),
+        eta,
+        current_function.unwrap_or("")
+      );
+      let _ = std::io::stderr().flush();
+    }
+
+    let path = match &self.path {
+      Some(path) => path,
+      None => return,
+    };
+    let due = match self.last_write {
+      Some(last_write) => last_write.elapsed() >= WRITE_INTERVAL,
+      None => true,
+    };
+    if !due && !finished {
+      return;
+    }
+
+    let snapshot = ProgressSnapshot {
+      crate_name: self.crate_name.clone(),
+      total: self.total,
+      done: self.done,
+      failed: self.failed,
+      elapsed,
+      eta,
+      current_function: current_function.map(str::to_string),
+      finished,
+    };
+
+    // Write to a temporary file first so readers never see a partial snapshot.
+    let tmp_path = path.with_extension("tmp");
+    let json = serde_json::to_string(&snapshot).unwrap();
+    if let Err(e) = fs::write(&tmp_path, json).and_then(|_| fs::rename(&tmp_path, path)) {
+      log::warn!("Failed to write progress to {}: {e}", path.display());
+    }
+    self.last_write = Some(Instant::now());
+  }
+}
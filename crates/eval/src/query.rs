@@ -0,0 +1,395 @@
+use std::{fmt::Write, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+use flowistry::{infoflow::Direction, source_map::ToSpan};
+use flowistry_ide::focus::PlaceInfo;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::{BytePos, Span, SyntaxContext};
+
+use crate::{
+  bodies::{self, CandidateBody},
+  visitor::direction_slice,
+};
+
+/// A single slicing criterion identified by its source location, either
+/// `file:line:column` (1-indexed) or `file:start-end` (byte offsets into the file).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryTarget {
+  LineColumn {
+    file: String,
+    line: usize,
+    column: usize,
+  },
+  ByteRange {
+    file: String,
+    start: usize,
+    end: usize,
+  },
+}
+
+impl FromStr for QueryTarget {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let malformed =
+      || anyhow!("expected file:line:column or file:start-end, found `{}`", s);
+    // Split from the right, since the file itself may contain `:`, e.g. `C:\src\a.rs`.
+    let (rest, last) = s.rsplit_once(':').ok_or_else(malformed)?;
+    if let Some((start, end)) = last.split_once('-') {
+      if rest.is_empty() {
+        return Err(malformed());
+      }
+      let (start, end) = (start.parse()?, end.parse()?);
+      if start > end {
+        bail!("byte range {}-{} ends before it starts", start, end);
+      }
+      return Ok(QueryTarget::ByteRange {
+        file: rest.to_string(),
+        start,
+        end,
+      });
+    }
+
+    let (file, line) = rest.rsplit_once(':').ok_or_else(malformed)?;
+    if file.is_empty() {
+      return Err(malformed());
+    }
+    let (line, column) = (line.parse()?, last.parse()?);
+    if line == 0 || column == 0 {
+      bail!("lines and columns are 1-indexed");
+    }
+    Ok(QueryTarget::LineColumn {
+      file: file.to_string(),
+      line,
+      column,
+    })
+  }
+}
+
+impl QueryTarget {
+  fn file(&self) -> &str {
+    match self {
+      QueryTarget::LineColumn { file, .. } | QueryTarget::ByteRange { file, .. } => file,
+    }
+  }
+
+  /// Resolves the target to a span in the first loaded source file whose name ends
+  /// with the target's file.
+  pub fn to_span(&self, tcx: TyCtxt<'_>) -> Result<Span> {
+    let source_map = tcx.sess.source_map();
+    let files = source_map.files();
+    let file = files
+      .iter()
+      .find(|file| file.name.prefer_local().to_string().ends_with(self.file()))
+      .ok_or_else(|| anyhow!("no source file matching `{}`", self.file()))?;
+
+    let (lo, hi) = match self {
+      QueryTarget::LineColumn { line, column, .. } => {
+        if *line > file.count_lines() {
+          bail!("{} only has {} lines", self.file(), file.count_lines());
+        }
+        let text = file
+          .get_line(line - 1)
+          .with_context(|| format!("no source for {}", self.file()))?;
+        // Columns count characters, so they are converted to a byte offset. The
+        // column just past the end of the line is allowed.
+        let offset = match text.char_indices().nth(column - 1) {
+          Some((offset, _)) => offset,
+          None if *column == text.chars().count() + 1 => text.len(),
+          None => bail!(
+            "line {} of {} only has {} columns",
+            line,
+            self.file(),
+            text.chars().count()
+          ),
+        };
+        let pos = file.line_bounds(line - 1).start + BytePos(offset as u32);
+        (pos, pos)
+      }
+      QueryTarget::ByteRange { start, end, .. } => {
+        let len = (file.end_pos - file.start_pos).0 as usize;
+        if *end > len {
+          bail!(
+            "byte range {}-{} is past the end of {} ({} bytes)",
+            start,
+            end,
+            self.file(),
+            len
+          );
+        }
+        (
+          file.start_pos + BytePos(*start as u32),
+          file.start_pos + BytePos(*end as u32),
+        )
+      }
+    };
+
+    Ok(Span::new(lo, hi, SyntaxContext::root(), None))
+  }
+}
+
+const DIM: &str = "\x1b[2m";
+const CRITERION: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders the source of `body_span` with every byte outside of `slice` faded, the
+/// same way Focus Mode fades irrelevant code, and the criterion highlighted.
+///
+/// Without `color`, lines are marked in the gutter instead: `>` for lines of the
+/// criterion, `*` for other lines in the slice.
+fn render(
+  tcx: TyCtxt<'_>,
+  body_span: Span,
+  criterion: Span,
+  slice: &[Span],
+  color: bool,
+) -> Result<String> {
+  if !color {
+    return render_plain(tcx, body_span, criterion, slice);
+  }
+
+  let source_map = tcx.sess.source_map();
+  let snippet = source_map
+    .span_to_snippet(body_span)
+    .map_err(|_| anyhow!("could not get source for {:?}", body_span))?;
+  let first_line = source_map.lookup_char_pos(body_span.lo()).line;
+
+  let style_at = |pos: BytePos| {
+    if criterion.lo() <= pos && pos < criterion.hi() {
+      CRITERION
+    } else if slice.iter().any(|span| span.lo() <= pos && pos < span.hi()) {
+      RESET
+    } else {
+      DIM
+    }
+  };
+
+  let mut output = format!("{:>5} | ", first_line);
+  let mut line = first_line;
+  let mut current_style = RESET;
+  for (offset, c) in snippet.char_indices() {
+    if c == '\n' {
+      line += 1;
+      write!(output, "{RESET}\n{:>5} | ", line).unwrap();
+      current_style = RESET;
+      continue;
+    }
+
+    let style = style_at(body_span.lo() + BytePos(offset as u32));
+    if style != current_style {
+      output.push_str(style);
+      current_style = style;
+    }
+    output.push(c);
+  }
+  output.push_str(RESET);
+
+  Ok(output)
+}
+
+fn render_plain(
+  tcx: TyCtxt<'_>,
+  body_span: Span,
+  criterion: Span,
+  slice: &[Span],
+) -> Result<String> {
+  let source_map = tcx.sess.source_map();
+  let snippet = source_map
+    .span_to_snippet(body_span)
+    .map_err(|_| anyhow!("could not get source for {:?}", body_span))?;
+  let first_line = source_map.lookup_char_pos(body_span.lo()).line;
+
+  let mut output = String::new();
+  let mut lo = body_span.lo();
+  for (i, text) in snippet.split('\n').enumerate() {
+    let hi = lo + BytePos(text.len() as u32);
+    let overlaps = |span: &Span| span.lo() < hi && lo < span.hi();
+    let marker = if overlaps(&criterion) {
+      '>'
+    } else if slice.iter().any(overlaps) {
+      '*'
+    } else {
+      ' '
+    };
+    if i > 0 {
+      output.push('\n');
+    }
+    write!(output, "{:>5} {marker}| {text}", first_line + i).unwrap();
+    lo = hi + BytePos(1);
+  }
+
+  Ok(output)
+}
+
+/// A slicing criterion resolved from a [`QueryTarget`].
+pub(crate) struct Criterion {
+  /// The innermost body containing the target.
+  pub body: CandidateBody,
+  pub span: Span,
+  pub place_info: PlaceInfo,
+}
+
+/// Finds the innermost function containing `target`, and the smallest place in it
+/// that overlaps the target.
+pub(crate) fn find_criterion(tcx: TyCtxt<'_>, target: &QueryTarget) -> Result<Criterion> {
+  find_criterion_in(tcx, &bodies::collect_bodies(tcx), target)
+}
+
+/// Like [`find_criterion`], but only searches `bodies`.
+pub(crate) fn find_criterion_in(
+  tcx: TyCtxt<'_>,
+  bodies: &[CandidateBody],
+  target: &QueryTarget,
+) -> Result<Criterion> {
+  let target_span = target.to_span(tcx)?;
+
+  // Closures are nested in their parent's span, so pick the innermost body.
+  let body = bodies
+    .iter()
+    .filter(|body| body.span.contains(target_span))
+    .min_by_key(|body| body.span.hi() - body.span.lo())
+    .cloned()
+    .ok_or_else(|| anyhow!("no function contains {:?}", target))?;
+
+  // Directional slices are only computed in debug mode.
+  fluid_let::fluid_set!(flowistry_ide::FOCUS_DEBUG, true);
+  let focus = flowistry_ide::focus(tcx, body.body_id)?;
+
+  // Pick the smallest place that overlaps the target, so a location inside
+  // `x.field` picks `x.field` rather than `x`.
+  let mut candidates = focus
+    .place_info
+    .into_iter()
+    .map(|info| Ok((info.range.to_span(tcx)?, info)))
+    .collect::<Result<Vec<_>>>()?;
+  candidates.retain(|(span, _)| {
+    span.contains(target_span) || (!target_span.is_empty() && target_span.contains(*span))
+  });
+  candidates.sort_by_key(|(span, _)| span.hi() - span.lo());
+  let (span, place_info) = candidates
+    .into_iter()
+    .next()
+    .ok_or_else(|| anyhow!("no slicing criterion at {:?}", target))?;
+
+  Ok(Criterion {
+    body,
+    span,
+    place_info,
+  })
+}
+
+/// Slices from the criterion at `target` and renders its forward, backward and
+/// combined slices over the source of the enclosing function, in color if stdout is
+/// a terminal.
+pub fn query(tcx: TyCtxt<'_>, target: &QueryTarget) -> Result<String> {
+  let criterion = find_criterion(tcx, target)?;
+  let color = atty::is(atty::Stream::Stdout);
+
+  let mut output = format!(
+    "{} in {}\n",
+    tcx
+      .sess
+      .source_map()
+      .span_to_diagnostic_string(criterion.span),
+    criterion.body.function_path
+  );
+  for direction in [Direction::Forward, Direction::Backward, Direction::Both] {
+    let slice = direction_slice(&criterion.place_info, direction)
+      .iter()
+      .map(|range| range.to_span(tcx))
+      .collect::<Result<Vec<_>>>()?;
+    write!(output, "\n{direction:?} slice:\n").unwrap();
+    output.push_str(&render(
+      tcx,
+      criterion.body.span,
+      criterion.span,
+      &slice,
+      color,
+    )?);
+    output.push('\n');
+  }
+
+  Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn line_column(file: &str, line: usize, column: usize) -> QueryTarget {
+    QueryTarget::LineColumn {
+      file: file.into(),
+      line,
+      column,
+    }
+  }
+
+  fn byte_range(file: &str, start: usize, end: usize) -> QueryTarget {
+    QueryTarget::ByteRange {
+      file: file.into(),
+      start,
+      end,
+    }
+  }
+
+  #[test]
+  fn parses_line_and_column() {
+    assert_eq!(
+      "src/lib.rs:12:5".parse::<QueryTarget>().unwrap(),
+      line_column("src/lib.rs", 12, 5)
+    );
+  }
+
+  #[test]
+  fn parses_byte_ranges() {
+    assert_eq!(
+      "src/lib.rs:10-20".parse::<QueryTarget>().unwrap(),
+      byte_range("src/lib.rs", 10, 20)
+    );
+    assert_eq!(
+      "src/lib.rs:7-7".parse::<QueryTarget>().unwrap(),
+      byte_range("src/lib.rs", 7, 7)
+    );
+  }
+
+  #[test]
+  fn rejects_backwards_ranges() {
+    assert!("src/lib.rs:20-10".parse::<QueryTarget>().is_err());
+  }
+
+  #[test]
+  fn rejects_zero_indexed_positions() {
+    assert!("src/lib.rs:0:5".parse::<QueryTarget>().is_err());
+    assert!("src/lib.rs:12:0".parse::<QueryTarget>().is_err());
+  }
+
+  #[test]
+  fn rejects_malformed_targets() {
+    for target in [
+      "src/lib.rs",
+      "src/lib.rs:12",
+      ":10-20",
+      ":12:5",
+      "src/lib.rs:a:5",
+      "src/lib.rs:10-",
+    ] {
+      assert!(target.parse::<QueryTarget>().is_err(), "{target}");
+    }
+  }
+
+  #[test]
+  fn keeps_colons_in_file_names() {
+    assert_eq!(
+      r"C:\src\lib.rs:10-20".parse::<QueryTarget>().unwrap(),
+      byte_range(r"C:\src\lib.rs", 10, 20)
+    );
+    assert_eq!(
+      r"C:\src\lib.rs:12:5".parse::<QueryTarget>().unwrap(),
+      line_column(r"C:\src\lib.rs", 12, 5)
+    );
+    assert_eq!(
+      "a:b.rs:3:4".parse::<QueryTarget>().unwrap(),
+      line_column("a:b.rs", 3, 4)
+    );
+  }
+}
@@ -0,0 +1,248 @@
+//! Crate-level rollups of eval results by module and by impl self type, to find the
+//! parts of a crate whose slices are least focused.
+//!
+//! Groups are read off each row's [stable ID](crate::stable_id), e.g.
+//! `rayon::iter::<Vec<T> as Foo>::bar` is in module `rayon::iter` and type `Vec<T>`.
+//! Rows without a stable ID fall back to `function_path`, whose impls are only
+//! numbered (`{impl#3}`), so their type is the impl's path instead.
+
+use std::collections::{HashMap, HashSet};
+
+use flowistry::infoflow::Direction;
+use serde::{Deserialize, Serialize};
+
+use crate::{schema::EvalResult, utils::fraction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grouping {
+  Module,
+  Type,
+}
+
+/// Slice sizes of one module or type, for one configuration and direction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupSummary {
+  pub grouping: Grouping,
+  pub group: String,
+  pub config: String,
+  pub direction: Direction,
+  pub num_functions: usize,
+  pub num_criteria: usize,
+  pub mean_relevant_token_fraction: f64,
+  pub mean_relevant_line_fraction: f64,
+  /// Fraction of the group's criteria whose slice covers at least half the lines of
+  /// its function.
+  pub unfocused_fraction: f64,
+}
+
+/// Splits a path on `::`, except inside the angle brackets of impl components.
+fn components(path: &str) -> Vec<&str> {
+  let mut components = Vec::new();
+  let mut depth = 0usize;
+  let mut start = 0;
+  let bytes = path.as_bytes();
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'<' => depth += 1,
+      // Not the arrow of a function pointer type.
+      b'>' if i == 0 || bytes[i - 1] != b'-' => depth = depth.saturating_sub(1),
+      b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+        components.push(&path[start .. i]);
+        start = i + 2;
+        i += 1;
+      }
+      _ => {}
+    }
+    i += 1;
+  }
+  components.push(&path[start ..]);
+  components
+}
+
+fn is_impl(component: &str) -> bool {
+  component.starts_with('<') || component.starts_with("{impl")
+}
+
+/// The self type of an impl component, e.g. `Vec<T>` for `<Vec<T> as Foo>`.
+fn self_type(component: &str) -> &str {
+  let inner = &component[1 .. component.len() - 1];
+  let mut depth = 0usize;
+  for (i, c) in inner.char_indices() {
+    match c {
+      '<' | '(' | '[' => depth += 1,
+      '>' if inner[.. i].ends_with('-') => {}
+      '>' | ')' | ']' => depth = depth.saturating_sub(1),
+      _ if depth == 0 && inner[i ..].starts_with(" as ") => return &inner[.. i],
+      _ => {}
+    }
+  }
+  inner
+}
+
+/// The module and, for functions in an impl, the self type of a function path.
+///
+/// Everything before the first impl is taken to be the module. Functions outside of
+/// impls are assumed not to be nested in other functions.
+pub fn module_and_type(path: &str) -> (String, Option<String>) {
+  let mut components = components(path);
+  while components.len() > 1 && components.last().unwrap().starts_with("{closure") {
+    components.pop();
+  }
+  // `def_path_debug_str` suffixes the crate name with its disambiguator.
+  if let Some(krate) = components.first_mut() {
+    *krate = krate.split('[').next().unwrap();
+  }
+
+  match components.iter().position(|component| is_impl(component)) {
+    Some(index) => {
+      let module = components[.. index].join("::");
+      let ty = if components[index].starts_with('<') {
+        self_type(components[index]).to_string()
+      } else {
+        components[..= index].join("::")
+      };
+      (module, Some(ty))
+    }
+    None => (components[.. components.len() - 1].join("::"), None),
+  }
+}
+
+/// Aggregates per-criterion results into one summary per group, configuration and
+/// direction, least focused (largest mean line fraction) first.
+pub fn summarize(results: &[EvalResult], grouping: Grouping) -> Vec<GroupSummary> {
+  let mut groups: HashMap<(String, &str, u8), Vec<&EvalResult>> = HashMap::new();
+  for result in results {
+    let path = result.stable_id.as_deref().unwrap_or(&result.function_path);
+    let (module, ty) = module_and_type(path);
+    let group = match grouping {
+      Grouping::Module => module,
+      Grouping::Type => match ty {
+        Some(ty) => ty,
+        None => continue,
+      },
+    };
+    groups
+      .entry((group, &result.config, result.direction as u8))
+      .or_default()
+      .push(result);
+  }
+
+  let mut summaries = groups
+    .into_iter()
+    .map(|((group, config, _), rows)| {
+      let n = rows.len() as f64;
+      let line_fractions = rows
+        .iter()
+        .map(|row| fraction(row.num_relevant_lines, row.num_lines))
+        .collect::<Vec<_>>();
+      GroupSummary {
+        grouping,
+        group,
+        config: config.to_string(),
+        direction: rows[0].direction,
+        num_functions: rows
+          .iter()
+          .map(|row| &row.function_path)
+          .collect::<HashSet<_>>()
+          .len(),
+        num_criteria: rows.len(),
+        mean_relevant_token_fraction: rows
+          .iter()
+          .map(|row| fraction(row.num_relevant_tokens, row.num_tokens))
+          .sum::<f64>()
+          / n,
+        mean_relevant_line_fraction: line_fractions.iter().sum::<f64>() / n,
+        unfocused_fraction: line_fractions.iter().filter(|f| **f >= 0.5).count() as f64
+          / n,
+      }
+    })
+    .collect::<Vec<_>>();
+
+  summaries.sort_by(|a, b| {
+    b.mean_relevant_line_fraction
+      .total_cmp(&a.mean_relevant_line_fraction)
+      .then_with(|| a.group.cmp(&b.group))
+      .then_with(|| a.config.cmp(&b.config))
+      .then_with(|| (a.direction as u8).cmp(&(b.direction as u8)))
+  });
+  summaries
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn group(module: &str, ty: Option<&str>) -> (String, Option<String>) {
+    (module.to_string(), ty.map(str::to_string))
+  }
+
+  #[test]
+  fn free_functions_have_no_type() {
+    assert_eq!(
+      module_and_type("rayon::iter::map"),
+      group("rayon::iter", None)
+    );
+    assert_eq!(
+      module_and_type("rayon[1a2b]::iter::map"),
+      group("rayon::iter", None)
+    );
+  }
+
+  #[test]
+  fn closures_belong_to_their_function() {
+    assert_eq!(
+      module_and_type("krate::m::f::{closure#0}::{closure#1}"),
+      group("krate::m", None)
+    );
+    assert_eq!(
+      module_and_type("krate::<S>::f::{closure#0}"),
+      group("krate", Some("S"))
+    );
+  }
+
+  #[test]
+  fn impls_group_by_self_type() {
+    assert_eq!(
+      module_and_type("rayon::iter::<Vec<T> as Foo>::bar"),
+      group("rayon::iter", Some("Vec<T>"))
+    );
+    assert_eq!(
+      module_and_type("krate::<T as std::ops::Add>::add"),
+      group("krate", Some("T"))
+    );
+  }
+
+  #[test]
+  fn nested_generics_stay_in_one_component() {
+    assert_eq!(
+      module_and_type("krate::m::<HashMap<K, Vec<V>> as Extend<(K, V)>>::extend"),
+      group("krate::m", Some("HashMap<K, Vec<V>>"))
+    );
+    assert_eq!(
+      module_and_type("krate::<<T as Tr>::Out as std::fmt::Debug>::fmt"),
+      group("krate", Some("<T as Tr>::Out"))
+    );
+  }
+
+  #[test]
+  fn function_pointer_arrows_are_not_brackets() {
+    assert_eq!(
+      module_and_type("krate::<fn(u8) -> Vec<u8> as Foo>::call"),
+      group("krate", Some("fn(u8) -> Vec<u8>"))
+    );
+    assert_eq!(
+      module_and_type("krate::<Box<dyn Fn() -> u8>>::new"),
+      group("krate", Some("Box<dyn Fn() -> u8>"))
+    );
+  }
+
+  #[test]
+  fn numbered_impls_group_by_path() {
+    assert_eq!(
+      module_and_type("rayon[1a2b]::iter::{impl#3}::next"),
+      group("rayon::iter", Some("rayon::iter::{impl#3}"))
+    );
+  }
+}
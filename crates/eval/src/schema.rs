@@ -0,0 +1,438 @@
+//! The format of the result files written by the eval driver, and a reader that
+//! validates them and upgrades files written by older versions of the driver.
+//!
+//! Result files are JSON arrays of [`EvalResult`], one per slicing criterion,
+//! analysis configuration and direction. Every row records the schema version it was
+//! written with, so files from different driver versions can be mixed.
+//!
+//! Version history:
+//! * 1: the original format, without `schema_version`.
+//! * 2: adds `schema_version`, `config`, the MIR [`FunctionFeatures`] and the
+//!   whole-program (`*_recursive`) slice sizes.
+//! * 3: adds `stable_id` and `content_hash`.
+//! * 4: adds the sizes of the [`crate::baseline`] slices.
+//! * 5: adds `num_statements` and `num_relevant_statements`.
+//! * 6: adds the distances between the criterion and its relevant lines.
+//! * 7: adds `precision`, `recall` and `f1` against [`crate::annotations`].
+//! * 8: adds `num_unmatched_recursive`.
+
+use std::{fmt, fs, fs::File, io::BufReader, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use flowistry::{infoflow::Direction, source_map::Range};
+use serde::{
+  de::{self, SeqAccess, Visitor},
+  Deserialize, Deserializer, Serialize,
+};
+use serde_json::Value;
+
+pub use crate::features::FunctionFeatures;
+
+/// The schema version written by this version of the driver.
+pub const SCHEMA_VERSION: u32 = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalResult {
+  /// Version of this schema the row was written with.
+  pub schema_version: u32,
+
+  // function-level data
+  /// Source range of the whole function item, including its signature.
+  pub function_range: Range,
+  /// `def_path_debug_str` of the function.
+  pub function_path: String,
+  /// Identifier of the function that is stable across revisions of the crate, see
+  /// [`crate::stable_id`]. Absent for rows upgraded from versions before 3.
+  pub stable_id: Option<String>,
+  /// Whitespace-insensitive hash of the function's source.
+  pub content_hash: Option<String>,
+  /// Number of MIR locations in the function body.
+  pub num_instructions: usize,
+  /// MIR structural features, absent for rows upgraded from version 1.
+  #[serde(flatten)]
+  pub features: Option<FunctionFeatures>,
+  /// Number of tokens in the function body.
+  pub num_tokens: usize,
+  /// Number of source lines containing at least one token of the body.
+  pub num_lines: usize,
+  /// Number of statements and block tail expressions in the body, see
+  /// [`crate::statements`]. Absent for rows upgraded from versions before 5.
+  pub num_statements: Option<usize>,
+
+  // sample-level parameters
+  /// Source range of the slicing criterion.
+  pub range: Range,
+  /// Name of the analysis configuration the slice was computed under.
+  pub config: String,
+  pub direction: Direction,
+
+  // sample-level data
+  /// Number of body tokens overlapping the slice.
+  pub num_relevant_tokens: usize,
+  /// Number of lines containing a relevant token.
+  pub num_relevant_lines: usize,
+  /// Number of statements containing a relevant token.
+  pub num_relevant_statements: Option<usize>,
+  /// Number of body lines between the first and third quartiles of relevant lines.
+  pub line_iqr: usize,
+  /// Largest number of lines between the criterion and a relevant line. This and
+  /// the following distances are absent for rows upgraded from versions before 6.
+  pub max_line_distance: Option<usize>,
+  /// Mean number of lines between the criterion and each relevant line, counting
+  /// lines of the criterion itself as 0.
+  pub mean_line_distance: Option<f64>,
+  /// Number of relevant lines above the criterion.
+  pub num_relevant_lines_before: Option<usize>,
+  /// Number of relevant lines below the criterion.
+  pub num_relevant_lines_after: Option<usize>,
+  /// Quality of the slice's lines against hand-annotated relevant lines, only
+  /// present for annotated criteria when run with `ANNOTATIONS_PATH`.
+  pub precision: Option<f64>,
+  pub recall: Option<f64>,
+  pub f1: Option<f64>,
+  /// Seconds spent computing all slices of the function.
+  pub duration: f64,
+
+  // whole-program comparison, only present when run with `COMPARE_RECURSIVE`
+  pub num_relevant_tokens_recursive: Option<usize>,
+  pub num_relevant_lines_recursive: Option<usize>,
+  pub recursive_duration: Option<f64>,
+  /// Number of places that only one of the modular and whole-program analyses
+  /// returned, whose rows have no recursive slice sizes. Absent if the whole-program
+  /// slices were not computed or for rows upgraded from versions before 8.
+  pub num_unmatched_recursive: Option<usize>,
+
+  // textual baselines, absent for rows upgraded from versions before 4
+  /// Number of body tokens that share an identifier with the criterion.
+  pub num_baseline_identifier_tokens: Option<usize>,
+  pub num_baseline_identifier_lines: Option<usize>,
+  /// Number of body tokens in the name-based def-use closure of the criterion.
+  pub num_baseline_def_use_tokens: Option<usize>,
+  pub num_baseline_def_use_lines: Option<usize>,
+}
+
+impl EvalResult {
+  /// Checks invariants that every row written by the driver satisfies.
+  pub fn validate(&self) -> Result<()> {
+    ensure!(
+      self.schema_version <= SCHEMA_VERSION,
+      "schema version {} is newer than the supported version {}",
+      self.schema_version,
+      SCHEMA_VERSION
+    );
+    ensure!(
+      self.num_relevant_tokens <= self.num_tokens,
+      "{} relevant tokens out of {}",
+      self.num_relevant_tokens,
+      self.num_tokens
+    );
+    ensure!(
+      self.num_relevant_lines <= self.num_lines,
+      "{} relevant lines out of {}",
+      self.num_relevant_lines,
+      self.num_lines
+    );
+    ensure!(
+      self.line_iqr <= self.num_lines,
+      "line IQR {} exceeds {} lines",
+      self.line_iqr,
+      self.num_lines
+    );
+    if let (Some(relevant), Some(total)) =
+      (self.num_relevant_statements, self.num_statements)
+    {
+      ensure!(
+        relevant <= total,
+        "{} relevant statements out of {}",
+        relevant,
+        total
+      );
+    }
+    if let (Some(before), Some(after)) = (
+      self.num_relevant_lines_before,
+      self.num_relevant_lines_after,
+    ) {
+      ensure!(
+        before + after <= self.num_relevant_lines,
+        "{} relevant lines before and {} after the criterion out of {}",
+        before,
+        after,
+        self.num_relevant_lines
+      );
+    }
+    Ok(())
+  }
+}
+
+/// Rewrites a serialized row of any older version into the current version.
+pub fn upgrade(mut row: Value) -> Result<Value> {
+  let fields = match row.as_object_mut() {
+    Some(fields) => fields,
+    None => bail!("expected an object, found {}", row),
+  };
+
+  let version = match fields.get("schema_version") {
+    Some(version) => version
+      .as_u64()
+      .with_context(|| format!("invalid schema version {}", version))?
+      as u32,
+    None => 1,
+  };
+
+  if version < 2 {
+    fields.insert("config".into(), "modular".into());
+    for key in [
+      "num_relevant_tokens_recursive",
+      "num_relevant_lines_recursive",
+      "recursive_duration",
+    ] {
+      fields.insert(key.into(), Value::Null);
+    }
+  }
+
+  if version < 3 {
+    fields.insert("stable_id".into(), Value::Null);
+    fields.insert("content_hash".into(), Value::Null);
+  }
+
+  if version < 4 {
+    for key in [
+      "num_baseline_identifier_tokens",
+      "num_baseline_identifier_lines",
+      "num_baseline_def_use_tokens",
+      "num_baseline_def_use_lines",
+    ] {
+      fields.insert(key.into(), Value::Null);
+    }
+  }
+
+  if version < 5 {
+    fields.insert("num_statements".into(), Value::Null);
+    fields.insert("num_relevant_statements".into(), Value::Null);
+  }
+
+  if version < 6 {
+    for key in [
+      "max_line_distance",
+      "mean_line_distance",
+      "num_relevant_lines_before",
+      "num_relevant_lines_after",
+    ] {
+      fields.insert(key.into(), Value::Null);
+    }
+  }
+
+  if version < 7 {
+    for key in ["precision", "recall", "f1"] {
+      fields.insert(key.into(), Value::Null);
+    }
+  }
+
+  if version < 8 {
+    fields.insert("num_unmatched_recursive".into(), Value::Null);
+  }
+
+  fields.insert("schema_version".into(), version.max(SCHEMA_VERSION).into());
+  Ok(row)
+}
+
+/// Upgrades and validates one serialized row.
+fn parse_row(row: Value) -> Result<EvalResult> {
+  let result: EvalResult = serde_json::from_value(upgrade(row)?)?;
+  result.validate()?;
+  Ok(result)
+}
+
+/// Parses, upgrades and validates the rows of a result file.
+pub fn parse_results(json: &str) -> Result<Vec<EvalResult>> {
+  let rows: Vec<Value> = serde_json::from_str(json)?;
+  rows
+    .into_iter()
+    .enumerate()
+    .map(|(i, row)| parse_row(row).with_context(|| format!("in row {i}")))
+    .collect()
+}
+
+pub fn read_results(path: impl AsRef<Path>) -> Result<Vec<EvalResult>> {
+  let path = path.as_ref();
+  let json =
+    fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+  parse_results(&json).with_context(|| format!("parsing {}", path.display()))
+}
+
+struct RowVisitor<'a, F> {
+  f: &'a mut F,
+  error: &'a mut Option<anyhow::Error>,
+}
+
+impl<'de, F: FnMut(EvalResult) -> Result<()>> Visitor<'de> for RowVisitor<'_, F> {
+  type Value = ();
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "an array of eval results")
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+    let mut i = 0;
+    while let Some(row) = seq.next_element::<Value>()? {
+      let result = parse_row(row)
+        .with_context(|| format!("in row {i}"))
+        .and_then(&mut *self.f);
+      if let Err(e) = result {
+        *self.error = Some(e);
+        return Err(de::Error::custom("stopped reading rows"));
+      }
+      i += 1;
+    }
+    Ok(())
+  }
+}
+
+/// Like [`read_results`], but calls `f` on each row as it is parsed instead of
+/// loading the whole file, and stops at the first error of `f`.
+pub fn for_each_result(
+  path: impl AsRef<Path>,
+  mut f: impl FnMut(EvalResult) -> Result<()>,
+) -> Result<()> {
+  let path = path.as_ref();
+  let file = File::open(path).with_context(|| format!("reading {}", path.display()))?;
+  let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+  let mut error = None;
+  let result = (&mut deserializer).deserialize_seq(RowVisitor {
+    f: &mut f,
+    error: &mut error,
+  });
+  let parse = || -> Result<()> {
+    if let Some(error) = error {
+      return Err(error);
+    }
+    result?;
+    deserializer.end()?;
+    Ok(())
+  };
+  parse().with_context(|| format!("parsing {}", path.display()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  /// A row as written by the driver at `version`.
+  pub(crate) fn row(version: u32) -> Value {
+    let mut row = json!({
+      "function_range": { "start": 0, "end": 100, "filename": "lib.rs" },
+      "function_path": "krate::f",
+      "num_instructions": 12,
+      "num_tokens": 40,
+      "num_lines": 8,
+      "range": { "start": 10, "end": 11, "filename": "lib.rs" },
+      "direction": "Forward",
+      "num_relevant_tokens": 10,
+      "num_relevant_lines": 3,
+      "line_iqr": 2,
+      "duration": 0.5,
+    });
+    let fields = row.as_object_mut().unwrap();
+    let mut add = |added_in: u32, values: Value| {
+      if version >= added_in {
+        fields.extend(values.as_object().unwrap().clone());
+      }
+    };
+    add(
+      2,
+      json!({
+        "schema_version": version,
+        "config": "recursive",
+        "num_basic_blocks": 3,
+        "num_loops": 0,
+        "cyclomatic_complexity": 2,
+        "num_calls": 1,
+        "num_locals": 4,
+        "num_borrows": 1,
+        "has_unsafe": false,
+        "num_relevant_tokens_recursive": 12,
+        "num_relevant_lines_recursive": 4,
+        "recursive_duration": 1.5,
+      }),
+    );
+    add(3, json!({ "stable_id": "krate::f", "content_hash": "abc" }));
+    add(
+      4,
+      json!({
+        "num_baseline_identifier_tokens": 5,
+        "num_baseline_identifier_lines": 2,
+        "num_baseline_def_use_tokens": 6,
+        "num_baseline_def_use_lines": 3,
+      }),
+    );
+    add(
+      5,
+      json!({ "num_statements": 6, "num_relevant_statements": 2 }),
+    );
+    add(
+      6,
+      json!({
+        "max_line_distance": 3,
+        "mean_line_distance": 1.5,
+        "num_relevant_lines_before": 1,
+        "num_relevant_lines_after": 1,
+      }),
+    );
+    add(7, json!({ "precision": 0.5, "recall": 1.0, "f1": 0.75 }));
+    add(8, json!({ "num_unmatched_recursive": 0 }));
+    row
+  }
+
+  #[test]
+  fn upgrades_every_version() {
+    for version in 1 ..= SCHEMA_VERSION {
+      let result = parse_row(row(version)).unwrap();
+      assert_eq!(
+        result.schema_version, SCHEMA_VERSION,
+        "from version {version}"
+      );
+      assert_eq!(result.num_relevant_tokens, 10);
+
+      let expected = |added_in: u32| version >= added_in;
+      assert_eq!(
+        result.features.is_some(),
+        expected(2),
+        "from version {version}"
+      );
+      assert_eq!(
+        result.config,
+        if expected(2) { "recursive" } else { "modular" }
+      );
+      assert_eq!(result.num_relevant_tokens_recursive.is_some(), expected(2));
+      assert_eq!(result.stable_id.is_some(), expected(3));
+      assert_eq!(result.num_baseline_def_use_lines.is_some(), expected(4));
+      assert_eq!(result.num_statements.is_some(), expected(5));
+      assert_eq!(result.max_line_distance.is_some(), expected(6));
+      assert_eq!(result.f1.is_some(), expected(7));
+      assert_eq!(result.num_unmatched_recursive.is_some(), expected(8));
+    }
+  }
+
+  #[test]
+  fn rejects_newer_versions() {
+    let mut row = row(SCHEMA_VERSION);
+    row["schema_version"] = (SCHEMA_VERSION + 1).into();
+    assert!(parse_row(row).is_err());
+  }
+
+  #[test]
+  fn rejects_inconsistent_rows() {
+    let mut row = row(SCHEMA_VERSION);
+    row["num_relevant_lines"] = 9.into();
+    assert!(parse_row(row).is_err());
+  }
+
+  #[test]
+  fn reports_the_failing_row() {
+    let json = serde_json::to_string(&[row(1), json!({})]).unwrap();
+    let error = parse_results(&json).unwrap_err();
+    assert_eq!(error.to_string(), "in row 1");
+  }
+}
@@ -0,0 +1,264 @@
+//! A JSON-RPC 2.0 server on stdin/stdout that answers many slicing queries against
+//! one compilation of a crate, instead of re-running rustc for each.
+//!
+//! Messages are JSON objects, one per line. The methods are:
+//!
+//! * `functions`: every analyzable body, as `{index, function_path, stable_id,
+//!   function_range}`.
+//! * `slice`, with params `{"target": "src/lib.rs:10:5"}` in the syntax of `QUERY`:
+//!   the criterion's range and its forward, backward and combined slices.
+//! * `metrics`, with params `{"function": ...}` selected like `ONLY_RUN` and
+//!   optionally `"configs": [spec, ...]` in the syntax of `CONFIGS`: the function's
+//!   [`EvalResult`] rows.
+//! * `shutdown`: stops the server, which also stops at the end of stdin.
+//!
+//! Cargo does not pass its stdin on to rustc, so the server is started by running
+//! `flowistry-eval-driver` directly with `SERVE=1` and the rustc arguments that
+//! `cargo flowistry-eval` prints for the crate.
+
+use std::{
+  io::{self, BufRead, Write},
+  panic::{self, AssertUnwindSafe},
+};
+
+use anyhow::{anyhow, Result};
+use flowistry::{infoflow::Direction, source_map::Range};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::LOCAL_CRATE;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+  bodies::{self, CandidateBody},
+  config::AnalysisConfig,
+  progress::Progress,
+  query::{self, QueryTarget},
+  trace::Trace,
+  visitor::{direction_slice, EvalCrateVisitor, EvalOptions},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// A request that was well-formed but could not be answered, e.g. because no
+/// function contains the target.
+const QUERY_FAILED: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+  method: String,
+  #[serde(default)]
+  params: Value,
+}
+
+struct RpcError {
+  code: i64,
+  message: String,
+}
+
+impl RpcError {
+  fn new(code: i64, message: impl Into<String>) -> Self {
+    RpcError {
+      code,
+      message: message.into(),
+    }
+  }
+
+  fn failed(error: anyhow::Error) -> Self {
+    RpcError::new(QUERY_FAILED, format!("{error:#}"))
+  }
+}
+
+#[derive(Serialize)]
+struct FunctionInfo<'a> {
+  index: usize,
+  function_path: &'a str,
+  stable_id: &'a str,
+  function_range: &'a Range,
+}
+
+#[derive(Deserialize)]
+struct SliceParams {
+  target: String,
+}
+
+#[derive(Serialize)]
+struct SliceResult<'a> {
+  function_path: &'a str,
+  criterion: Range,
+  forward: &'a [Range],
+  backward: &'a [Range],
+  both: &'a [Range],
+}
+
+#[derive(Deserialize)]
+struct MetricsParams {
+  function: String,
+  configs: Option<Vec<String>>,
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+  serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+pub struct Server<'tcx> {
+  tcx: TyCtxt<'tcx>,
+  bodies: Vec<CandidateBody>,
+  configs: Vec<AnalysisConfig>,
+}
+
+impl<'tcx> Server<'tcx> {
+  /// Collects the crate's bodies once. `configs` are used for `metrics` requests
+  /// that do not name their own.
+  pub fn new(tcx: TyCtxt<'tcx>, configs: Vec<AnalysisConfig>) -> Self {
+    Server {
+      tcx,
+      bodies: bodies::collect_bodies(tcx),
+      configs,
+    }
+  }
+
+  fn functions(&self) -> Result<Value, RpcError> {
+    let functions = self
+      .bodies
+      .iter()
+      .map(|body| FunctionInfo {
+        index: body.index,
+        function_path: &body.function_path,
+        stable_id: &body.stable_id,
+        function_range: &body.function_range,
+      })
+      .collect::<Vec<_>>();
+    Ok(json!(functions))
+  }
+
+  fn slice(&self, params: SliceParams) -> Result<Value, RpcError> {
+    let target = params
+      .target
+      .parse::<QueryTarget>()
+      .map_err(|e| RpcError::new(INVALID_PARAMS, format!("{e:#}")))?;
+    let criterion = query::find_criterion_in(self.tcx, &self.bodies, &target)
+      .map_err(RpcError::failed)?;
+    let info = &criterion.place_info;
+    Ok(json!(SliceResult {
+      function_path: &criterion.body.function_path,
+      criterion: info.range.clone(),
+      forward: direction_slice(info, Direction::Forward),
+      backward: direction_slice(info, Direction::Backward),
+      both: direction_slice(info, Direction::Both),
+    }))
+  }
+
+  fn metrics(&self, params: MetricsParams) -> Result<Value, RpcError> {
+    let configs = match params.configs {
+      Some(specs) => specs
+        .iter()
+        .map(|spec| AnalysisConfig::parse(spec))
+        .collect::<Result<Vec<_>>>()
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("{e:#}")))?,
+      None => self.configs.clone(),
+    };
+    let bodies = bodies::filter_only_run(self.bodies.clone(), &params.function);
+    let body = match &bodies[..] {
+      [body] => body,
+      _ => {
+        return Err(RpcError::failed(anyhow!(
+          "`{}` matched {} functions, expected 1",
+          params.function,
+          bodies.len()
+        )));
+      }
+    };
+
+    let options = EvalOptions {
+      configs,
+      compare_recursive: false,
+      verify_determinism: false,
+      record_slices: false,
+      overlap_threshold: None,
+      annotations: Vec::new(),
+    };
+    let progress = Progress::new(self.tcx.crate_name(LOCAL_CRATE).to_string(), 1, None);
+    let mut visitor =
+      EvalCrateVisitor::new(progress, Trace::new(false), self.bodies.len(), options);
+    visitor.analyze(self.tcx, body);
+    Ok(json!(visitor.eval_results))
+  }
+
+  fn dispatch(&self, request: Request) -> Result<Value, RpcError> {
+    match request.method.as_str() {
+      "functions" => self.functions(),
+      "slice" => self.slice(parse_params(request.params)?),
+      "metrics" => self.metrics(parse_params(request.params)?),
+      "shutdown" => Ok(Value::Null),
+      method => Err(RpcError::new(
+        METHOD_NOT_FOUND,
+        format!("unknown method `{method}`"),
+      )),
+    }
+  }
+
+  /// Answers requests from stdin until `shutdown` or the end of the input.
+  pub fn serve(&self) -> Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    for line in stdin.lock().lines() {
+      let line = line?;
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      let (id, result, shutdown) = match serde_json::from_str::<Value>(&line) {
+        Err(e) => (
+          Value::Null,
+          Err(RpcError::new(PARSE_ERROR, e.to_string())),
+          false,
+        ),
+        Ok(message) => {
+          // Notifications have no ID and get no response.
+          let id = message.get("id").cloned();
+          match serde_json::from_value::<Request>(message) {
+            Err(e) => (
+              id.unwrap_or(Value::Null),
+              Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+              false,
+            ),
+            Ok(request) => {
+              let shutdown = request.method == "shutdown";
+              // A panic in the analysis of one function should not take down the
+              // compilation every other request relies on.
+              let result =
+                panic::catch_unwind(AssertUnwindSafe(|| self.dispatch(request)))
+                  .unwrap_or_else(|_| {
+                    Err(RpcError::new(INTERNAL_ERROR, "the analysis panicked"))
+                  });
+              match id {
+                Some(id) => (id, result, shutdown),
+                None if shutdown => break,
+                None => continue,
+              }
+            }
+          }
+        }
+      };
+
+      let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+          "jsonrpc": "2.0",
+          "id": id,
+          "error": { "code": error.code, "message": error.message },
+        }),
+      };
+      writeln!(stdout, "{response}")?;
+      stdout.flush()?;
+
+      if shutdown {
+        break;
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,150 @@
+//! Running the eval on a single-file crate given as a string, without cargo.
+
+use std::{env, io, path::Path, process::Command, thread};
+
+use anyhow::{anyhow, bail, Context, Result};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::{def_id::LOCAL_CRATE, source_map::FileLoader};
+
+use crate::{
+  bodies, config::AnalysisConfig, progress::Progress, schema::EvalResult, trace::Trace,
+  visitor, TcxCallbacks,
+};
+
+/// Name of the in-memory source file, as it appears in result ranges.
+pub const SOURCE_FILE_NAME: &str = "main.rs";
+
+/// Serves `source` as [`SOURCE_FILE_NAME`], and no other file.
+struct StringLoader(String);
+
+impl FileLoader for StringLoader {
+  fn file_exists(&self, path: &Path) -> bool {
+    path == Path::new(SOURCE_FILE_NAME)
+  }
+
+  fn read_file(&self, path: &Path) -> io::Result<String> {
+    if self.file_exists(path) {
+      Ok(self.0.clone())
+    } else {
+      Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} is not {}", path.display(), SOURCE_FILE_NAME),
+      ))
+    }
+  }
+}
+
+fn sysroot() -> Result<String> {
+  if let Ok(sysroot) = env::var("SYSROOT") {
+    return Ok(sysroot);
+  }
+  let output = Command::new("rustc")
+    .args(["--print", "sysroot"])
+    .output()
+    .context("could not run rustc to find the sysroot")?;
+  if !output.status.success() {
+    bail!(
+      "rustc --print sysroot failed with {}: {}",
+      output.status,
+      String::from_utf8_lossy(&output.stderr).trim()
+    );
+  }
+  Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Compiles `source` as a library crate and runs `f` on its type context. The
+/// sysroot is taken from `SYSROOT`, or else from `rustc` on the path.
+///
+/// Compiler diagnostics are printed to stderr, and an error is returned if `source`
+/// does not compile.
+///
+/// Flowistry caches borrowck facts in a thread-local that outlives the compiler
+/// session, so a second session on the same thread could read bodies freed by the
+/// first. Each call therefore compiles on a fresh thread, and code that calls
+/// flowistry directly must do the same when running more than one session.
+pub(crate) fn compile_source<T: Send + 'static>(
+  source: &str,
+  f: impl FnOnce(TyCtxt<'_>) -> T + Send + 'static,
+) -> Result<T> {
+  let args = [
+    "rustc",
+    SOURCE_FILE_NAME,
+    "--crate-type",
+    "lib",
+    "--edition",
+    "2021",
+    "-A",
+    "warnings",
+    "--sysroot",
+    &sysroot()?,
+  ]
+  .map(str::to_string);
+  let source = source.to_string();
+
+  thread::spawn(move || {
+    let mut f = Some(f);
+    let mut output = None;
+    let mut callbacks = TcxCallbacks(|tcx: TyCtxt<'_>| {
+      output = f.take().map(|f| f(tcx));
+    });
+    rustc_driver::catch_fatal_errors(|| {
+      let mut compiler = rustc_driver::RunCompiler::new(&args, &mut callbacks);
+      compiler.set_file_loader(Some(Box::new(StringLoader(source))));
+      compiler.run()
+    })
+    .and_then(|result| result)
+    .map_err(|_| anyhow!("{} failed to compile", SOURCE_FILE_NAME))?;
+    output.ok_or_else(|| anyhow!("{} failed to compile", SOURCE_FILE_NAME))
+  })
+  .join()
+  .map_err(|_| anyhow!("the compiler panicked on {}", SOURCE_FILE_NAME))?
+}
+
+/// Evaluates every function of `source`, compiled as a library crate, under each of
+/// `configs`, see [`compile_source`].
+pub fn eval_source(
+  source: &str,
+  configs: Vec<AnalysisConfig>,
+) -> Result<Vec<EvalResult>> {
+  compile_source(source, move |tcx| {
+    let bodies = bodies::collect_bodies(tcx);
+    let options = visitor::EvalOptions {
+      configs,
+      compare_recursive: false,
+      verify_determinism: false,
+      record_slices: false,
+      overlap_threshold: None,
+      annotations: Vec::new(),
+    };
+    let progress =
+      Progress::new(tcx.crate_name(LOCAL_CRATE).to_string(), bodies.len(), None);
+    let mut eval_visitor =
+      visitor::EvalCrateVisitor::new(progress, Trace::new(false), bodies.len(), options);
+    for body in &bodies {
+      eval_visitor.analyze(tcx, body);
+    }
+    eval_visitor.eval_results
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_serves_the_source_file() {
+    let loader = StringLoader("fn f() {}".into());
+    assert!(loader.file_exists(Path::new(SOURCE_FILE_NAME)));
+    assert_eq!(
+      loader.read_file(Path::new(SOURCE_FILE_NAME)).unwrap(),
+      "fn f() {}"
+    );
+    assert!(!loader.file_exists(Path::new("other.rs")));
+    assert!(loader.read_file(Path::new("other.rs")).is_err());
+  }
+
+  #[test]
+  fn missing_modules_fail_to_compile() {
+    assert!(compile_source("mod other;", |_| ()).is_err());
+  }
+}
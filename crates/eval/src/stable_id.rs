@@ -0,0 +1,144 @@
+//! Identifiers for functions that survive unrelated edits to a crate.
+//!
+//! `def_path_debug_str` numbers impl blocks by position (`{impl#3}`), so adding an
+//! impl anywhere shifts the paths of every function after it. Here impls are named
+//! by their self type and trait instead, e.g. `rayon::iter::<Vec<T> as Foo>::bar`.
+//! The body's content hash is kept separately, so results can be joined on the ID
+//! across revisions while still telling whether the function itself changed.
+
+use std::{fmt::Display, hash::Hash};
+
+use rustc_data_structures::stable_hasher::StableHasher;
+use rustc_hir::{def_id::DefId, definitions::DefPathData};
+use rustc_middle::ty::{print::with_no_trimmed_paths, TyCtxt};
+use rustc_span::Span;
+
+fn impl_component(self_ty: impl Display, trait_path: Option<impl Display>) -> String {
+  match trait_path {
+    Some(trait_path) => format!("<{self_ty} as {trait_path}>"),
+    None => format!("<{self_ty}>"),
+  }
+}
+
+fn closure_component(disambiguator: u32) -> String {
+  format!("{{closure#{disambiguator}}}")
+}
+
+/// Items that share a name within their parent, e.g. two `impl Trait` in argument
+/// position, are told apart by their disambiguator.
+fn named_component(name: impl Display, disambiguator: u32) -> String {
+  if disambiguator > 0 {
+    format!("{name}#{disambiguator}")
+  } else {
+    name.to_string()
+  }
+}
+
+fn path_component(tcx: TyCtxt<'_>, def_id: DefId) -> String {
+  let key = tcx.def_key(def_id);
+  let disambiguator = key.disambiguated_data.disambiguator;
+  match key.disambiguated_data.data {
+    DefPathData::CrateRoot => tcx.crate_name(def_id.krate).to_string(),
+    DefPathData::Impl => {
+      let self_ty = tcx.type_of(def_id);
+      with_no_trimmed_paths!(impl_component(
+        self_ty,
+        tcx
+          .impl_trait_ref(def_id)
+          .map(|trait_ref| trait_ref.print_only_trait_path())
+      ))
+    }
+    DefPathData::ClosureExpr => closure_component(disambiguator),
+    data => named_component(data, disambiguator),
+  }
+}
+
+/// The crate name, module path, impl self type and item name of `def_id`.
+pub fn stable_id(tcx: TyCtxt<'_>, def_id: DefId) -> String {
+  let mut components = Vec::new();
+  let mut current = Some(def_id);
+  while let Some(def_id) = current {
+    components.push(path_component(tcx, def_id));
+    current = tcx.def_key(def_id).parent.map(|index| DefId {
+      krate: def_id.krate,
+      index,
+    });
+  }
+  components.reverse();
+  components.join("::")
+}
+
+/// A hash of the source code of `span` that ignores changes to whitespace.
+pub fn content_hash(tcx: TyCtxt<'_>, span: Span) -> String {
+  let snippet = tcx
+    .sess
+    .source_map()
+    .span_to_snippet(span)
+    .unwrap_or_default();
+  hash_words(&snippet)
+}
+
+fn hash_words(snippet: &str) -> String {
+  let mut hasher = StableHasher::new();
+  for word in snippet.split_whitespace() {
+    word.hash(&mut hasher);
+  }
+  format!("{:016x}", hasher.finish::<u64>())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{bodies::collect_bodies, source::compile_source};
+
+  /// The stable IDs and content hashes of every body in `source`, sorted.
+  fn ids(source: &str) -> Vec<(String, String)> {
+    let mut ids = compile_source(source, |tcx| {
+      collect_bodies(tcx)
+        .into_iter()
+        .map(|body| (body.stable_id, body.content_hash))
+        .collect::<Vec<_>>()
+    })
+    .unwrap();
+    ids.sort();
+    ids
+  }
+
+  #[test]
+  fn formats_inherent_and_trait_impls() {
+    assert_eq!(impl_component("Vec<T>", None::<&str>), "<Vec<T>>");
+    assert_eq!(
+      impl_component("Vec<T>", Some("std::iter::Extend<T>")),
+      "<Vec<T> as std::iter::Extend<T>>"
+    );
+  }
+
+  #[test]
+  fn numbers_closures_and_repeated_names() {
+    assert_eq!(closure_component(0), "{closure#0}");
+    assert_eq!(named_component("foo", 0), "foo");
+    assert_eq!(named_component("foo", 2), "foo#2");
+  }
+
+  #[test]
+  fn content_hash_ignores_whitespace() {
+    let hash = hash_words("fn f() {\n  x + 1\n}");
+    assert_eq!(hash_words("fn f() { x + 1 }"), hash);
+    assert_ne!(hash_words("fn f() { x + 2 }"), hash);
+    assert_eq!(hash.len(), 16);
+  }
+
+  #[test]
+  fn ids_do_not_depend_on_impl_order() {
+    let header = "pub struct A; pub struct B; pub trait Named { fn name(&self) -> u32; }";
+    let inherent = "impl A { pub fn get(&self) -> u32 { 1 } }";
+    let trait_impl = "impl Named for B { fn name(&self) -> u32 { 2 } }";
+    let before = ids(&format!("{header}\n{inherent}\n{trait_impl}"));
+    let after = ids(&format!("{header}\n{trait_impl}\n{inherent}"));
+
+    let stable_ids = before.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
+    assert_eq!(stable_ids, ["main::<A>::get", "main::<B as Named>::name"]);
+    assert!(stable_ids.iter().all(|id| !id.contains("{impl#")));
+    assert_eq!(before, after);
+  }
+}
@@ -0,0 +1,83 @@
+//! Statement-granularity units of a function body, closer to what a reader skips
+//! when Focus Mode fades code than tokens or lines.
+//!
+//! The units are the HIR statements of every block in the body and the blocks' tail
+//! expressions. Units nest, e.g. the statements inside an `if`, so each token belongs
+//! to the innermost unit containing it, and a unit is relevant if any of its own
+//! tokens are. An `if` whose condition is irrelevant is then skipped even when its
+//! branches are not.
+
+use rustc_data_structures::fx::FxHashSet as HashSet;
+use rustc_hir::{
+  intravisit::{self, Visitor},
+  Block, BodyId, Stmt,
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+
+#[derive(Default)]
+struct UnitFinder {
+  spans: Vec<Span>,
+}
+
+impl<'tcx> Visitor<'tcx> for UnitFinder {
+  fn visit_stmt(&mut self, stmt: &'tcx Stmt<'tcx>) {
+    self.spans.push(stmt.span.source_callsite());
+    intravisit::walk_stmt(self, stmt);
+  }
+
+  fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
+    if let Some(tail) = block.expr {
+      self.spans.push(tail.span.source_callsite());
+    }
+    intravisit::walk_block(self, block);
+  }
+}
+
+pub struct Statements {
+  /// The unit each token belongs to, if any.
+  token_units: Vec<Option<usize>>,
+  num_statements: usize,
+}
+
+impl Statements {
+  pub fn build(tcx: TyCtxt<'_>, body_id: BodyId, token_spans: &[Span]) -> Self {
+    let body = tcx.hir().body(body_id);
+    let mut finder = UnitFinder::default();
+    finder.visit_body(body);
+
+    let mut units = finder
+      .spans
+      .into_iter()
+      .filter(|span| body.value.span.contains(*span))
+      .collect::<Vec<_>>();
+    // Innermost units first, so each token is claimed by the smallest unit.
+    units.sort_by_key(|span| (span.hi() - span.lo(), span.lo()));
+    units.dedup();
+
+    let token_units = token_spans
+      .iter()
+      .map(|token| units.iter().position(|unit| unit.contains(*token)))
+      .collect::<Vec<_>>();
+    let num_statements = token_units.iter().flatten().collect::<HashSet<_>>().len();
+
+    Statements {
+      token_units,
+      num_statements,
+    }
+  }
+
+  /// Number of units that own at least one token.
+  pub fn num_statements(&self) -> usize {
+    self.num_statements
+  }
+
+  /// Number of units owning at least one of `tokens`, given as token indices.
+  pub fn count_relevant(&self, tokens: impl IntoIterator<Item = usize>) -> usize {
+    tokens
+      .into_iter()
+      .filter_map(|idx| self.token_units[idx])
+      .collect::<HashSet<_>>()
+      .len()
+  }
+}
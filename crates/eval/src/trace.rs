@@ -0,0 +1,82 @@
+//! Timing of each phase of the eval as Chrome trace events, viewable in
+//! `chrome://tracing` or <https://ui.perfetto.dev>.
+
+use std::{fs, path::Path, time::Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// A complete ("X") event of the trace event format.
+#[derive(Debug, Serialize)]
+pub struct TraceEvent {
+  pub name: String,
+  pub cat: &'static str,
+  pub ph: &'static str,
+  /// Start time in microseconds since the trace started.
+  pub ts: f64,
+  /// Duration in microseconds.
+  pub dur: f64,
+  pub pid: u32,
+  pub tid: u32,
+  pub args: Value,
+}
+
+/// Collects trace events if enabled, and otherwise does nothing.
+pub struct Trace {
+  start: Instant,
+  events: Option<Vec<TraceEvent>>,
+}
+
+impl Trace {
+  pub fn new(enabled: bool) -> Self {
+    Trace {
+      start: Instant::now(),
+      events: enabled.then(Vec::new),
+    }
+  }
+
+  /// Records an event from `start` until now. Events about one function should pass
+  /// its path, so they can be told apart in the viewer.
+  pub fn complete(
+    &mut self,
+    cat: &'static str,
+    name: &str,
+    start: Instant,
+    function_path: Option<&str>,
+  ) {
+    let events = match &mut self.events {
+      Some(events) => events,
+      None => return,
+    };
+    let micros = |instant: Instant| {
+      instant.saturating_duration_since(self.start).as_secs_f64() * 1e6
+    };
+    let ts = micros(start);
+    events.push(TraceEvent {
+      name: name.to_string(),
+      cat,
+      ph: "X",
+      ts,
+      dur: micros(Instant::now()) - ts,
+      pid: std::process::id(),
+      tid: 0,
+      args: match function_path {
+        Some(path) => json!({ "function": path }),
+        None => json!({}),
+      },
+    });
+  }
+
+  /// Writes the events as a JSON trace file, if tracing is enabled.
+  pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+    if let Some(events) = &self.events {
+      let trace = json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+      });
+      fs::write(path, serde_json::to_string(&trace)?)?;
+    }
+    Ok(())
+  }
+}
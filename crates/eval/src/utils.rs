@@ -0,0 +1,56 @@
+//! Small helpers shared by the eval's modes.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use serde::de::DeserializeOwned;
+
+/// Reads a JSON input file, such as a list of fault-localization tasks.
+pub fn read_json<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
+  let path = path.as_ref();
+  let json =
+    fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+  serde_json::from_str(&json).with_context(|| format!("parsing {}", path.display()))
+}
+
+/// `n / total`, or 0 for an empty total, e.g. the fraction of a function's lines in a
+/// slice.
+pub fn fraction(n: usize, total: usize) -> f64 {
+  if total == 0 {
+    0.
+  } else {
+    n as f64 / total as f64
+  }
+}
+
+/// Escapes `s` for XML text and attribute values.
+pub fn to_xml_string(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// Every 1-indexed line that `span` touches, with the name of its file.
+pub fn span_lines(tcx: TyCtxt<'_>, span: Span) -> impl Iterator<Item = (String, usize)> {
+  let source_map = tcx.sess.source_map();
+  let start = source_map.lookup_char_pos(span.lo());
+  let end = source_map.lookup_char_pos(span.hi()).line;
+  let file = start.file.name.prefer_local().to_string();
+  (start.line ..= end).map(move |line| (file.clone(), line))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn escapes_xml() {
+    assert_eq!(
+      to_xml_string("<a href=\"b\">&</a>"),
+      "&lt;a href=&quot;b&quot;&gt;&amp;&lt;/a&gt;"
+    );
+  }
+}
@@ -1,38 +1,44 @@
-use std::{env, iter::FromIterator, time::Instant};
+use std::{
+  collections::BTreeSet, iter::FromIterator, ops::RangeInclusive, time::Instant,
+};
 
 use flowistry::{
+  extensions::{ContextMode, EvalMode, EVAL_MODE},
   infoflow::Direction,
   mir::{borrowck_facts, utils::BodyExt},
   source_map::{Range, SpanTree, ToSpan},
 };
-use log::info;
+use flowistry_ide::focus::PlaceInfo;
+use log::{info, warn};
 use rustc_ast::{
-  token::Token,
+  token::{Delimiter, Token, TokenKind},
   tokenstream::{TokenStream, TokenTree},
 };
-use rustc_data_structures::fx::FxHashSet as HashSet;
-use rustc_hir::{intravisit::Visitor, BodyId, ImplItemKind, ItemKind};
-use rustc_middle::{hir::nested_filter::OnlyBodies, ty::TyCtxt};
-use rustc_span::{source_map::Spanned, FileName, Span, SpanData, SyntaxContext};
-use serde::Serialize;
-
-#[derive(Debug, Serialize)]
-pub struct EvalResult {
-  function_range: Range,
-  function_path: String,
-  range: Range,
-  num_instructions: usize,
-  num_tokens: usize,
-  num_lines: usize,
-  direction: Direction,
-  num_relevant_tokens: usize,
-  num_relevant_lines: usize,
-  line_iqr: usize,
-  duration: f64,
-}
+use rustc_data_structures::fx::{FxHashMap as HashMap, FxHashSet as HashSet};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::{source_map::Spanned, FileName, Span, SpanData, Symbol, SyntaxContext};
+
+use crate::{
+  annotations::{self, Annotation, Quality},
+  baseline,
+  bodies::CandidateBody,
+  config::AnalysisConfig,
+  determinism::{self, Nondeterminism, RecordedSlice},
+  features::FunctionFeatures,
+  overlap::SliceOverlap,
+  progress::Progress,
+  schema::{EvalResult, SCHEMA_VERSION},
+  statements::Statements,
+  trace::Trace,
+};
 
 struct Tokens {
   spans: SpanTree<usize>,
+  token_spans: Vec<Span>,
+  /// The identifier of each token, if it is a non-reserved identifier.
+  idents: Vec<Option<Symbol>>,
+  /// The index of each token's statement, for the [`baseline`] slicers.
+  statements: Vec<usize>,
 }
 
 impl Tokens {
@@ -46,6 +52,27 @@ impl Tokens {
       .collect()
   }
 
+  /// Numbers statements in the same order as [`Tokens::flatten_stream`], starting a
+  /// new statement after every `;` and at both ends of every brace-delimited block.
+  fn statement_ids(stream: TokenStream, statement: &mut usize, ids: &mut Vec<usize>) {
+    for tree in stream.into_trees() {
+      match tree {
+        TokenTree::Token(token) => {
+          ids.push(*statement);
+          if token.kind == TokenKind::Semi {
+            *statement += 1;
+          }
+        }
+        TokenTree::Delimited(_, delim, stream) => {
+          let is_block = delim == Delimiter::Brace;
+          *statement += is_block as usize;
+          Self::statement_ids(stream, statement, ids);
+          *statement += is_block as usize;
+        }
+      }
+    }
+  }
+
   pub fn build(tcx: TyCtxt<'_>, span: Span, count: usize) -> Self {
     log::debug!("Tokens: {span:?}");
     let source_map = tcx.sess.source_map();
@@ -60,20 +87,42 @@ impl Tokens {
     );
 
     let token_stream = parser.parse_tokens();
+    let mut statements = Vec::new();
+    Self::statement_ids(token_stream.clone(), &mut 0, &mut statements);
     let tokens = Self::flatten_stream(token_stream);
+    let idents = tokens
+      .iter()
+      .map(|token| {
+        let (ident, _) = token.ident()?;
+        (!ident.is_reserved()).then(|| ident.name)
+      })
+      .collect();
     log::debug!(
       "{:?}",
       tokens.iter().map(|token| &token.kind).collect::<Vec<_>>()
     );
 
-    let spans = SpanTree::new(tokens.into_iter().enumerate().map(|(idx, token)| {
-      let lo = source_map.lookup_byte_offset(token.span.lo()).pos;
-      let hi = source_map.lookup_byte_offset(token.span.hi()).pos;
-      let span = Span::new(base + lo, base + hi, SyntaxContext::root(), None);
-      log::debug!("{span:?}");
-      Spanned { span, node: idx }
-    }));
-    Tokens { spans }
+    let token_spans = tokens
+      .into_iter()
+      .map(|token| {
+        let lo = source_map.lookup_byte_offset(token.span.lo()).pos;
+        let hi = source_map.lookup_byte_offset(token.span.hi()).pos;
+        let span = Span::new(base + lo, base + hi, SyntaxContext::root(), None);
+        log::debug!("{span:?}");
+        span
+      })
+      .collect::<Vec<_>>();
+    let spans =
+      SpanTree::new(token_spans.iter().enumerate().map(|(idx, span)| Spanned {
+        span: *span,
+        node: idx,
+      }));
+    Tokens {
+      spans,
+      token_spans,
+      idents,
+      statements,
+    }
   }
 
   pub fn total_tokens(&self) -> usize {
@@ -91,106 +140,127 @@ impl Tokens {
   }
 }
 
-pub trait BodyVisitor<'tcx> {
-  fn visit(&mut self, body_span: Span, body_id: BodyId, tcx: TyCtxt<'tcx>);
+/// How far the relevant lines of a slice extend from the lines of its criterion.
+struct LineDistances {
+  max: usize,
+  mean: f64,
+  num_before: usize,
+  num_after: usize,
 }
 
-struct BodyFinder<'tcx, 'a, V> {
-  pub tcx: TyCtxt<'tcx>,
-  pub visitor: &'a mut V,
-}
-
-impl<'tcx, V> Visitor<'tcx> for BodyFinder<'tcx, '_, V>
-where
-  V: BodyVisitor<'tcx>,
-{
-  type NestedFilter = OnlyBodies;
-
-  fn nested_visit_map(&mut self) -> Self::Map {
-    self.tcx.hir()
-  }
-
-  fn visit_nested_body(&mut self, id: BodyId) {
-    let hir = self.nested_visit_map();
-
-    // const/static items are considered to have bodies, so we want to exclude
-    // them from our search for functions
-    if !hir
-      .body_owner_kind(hir.body_owner_def_id(id))
-      .is_fn_or_closure()
-    {
-      return;
-    }
-
-    let owner = hir.body_owner(id);
-    let body_span = hir.span(owner);
-    if body_span.from_expansion() {
-      return;
+impl LineDistances {
+  fn compute(criterion: RangeInclusive<usize>, relevant_lines: &[usize]) -> Self {
+    let distances = relevant_lines
+      .iter()
+      .map(|line| {
+        if line < criterion.start() {
+          criterion.start() - line
+        } else {
+          line.saturating_sub(*criterion.end())
+        }
+      })
+      .collect::<Vec<_>>();
+    let num_before = relevant_lines
+      .iter()
+      .filter(|line| *line < criterion.start())
+      .count();
+    let num_after = relevant_lines
+      .iter()
+      .filter(|line| *line > criterion.end())
+      .count();
+
+    LineDistances {
+      max: distances.iter().copied().max().unwrap_or(0),
+      mean: if distances.is_empty() {
+        0.
+      } else {
+        distances.iter().sum::<usize>() as f64 / distances.len() as f64
+      },
+      num_before,
+      num_after,
     }
+  }
+}
 
-    self.visitor.visit(body_span, id, self.tcx);
+pub(crate) fn direction_slice(place_info: &PlaceInfo, direction: Direction) -> &[Range] {
+  match direction {
+    Direction::Both => &place_info.slice,
+    Direction::Forward => place_info.forward.as_ref().unwrap(),
+    Direction::Backward => place_info.backward.as_ref().unwrap(),
   }
 }
 
-pub fn visit_bodies<'tcx, V: BodyVisitor<'tcx>>(tcx: TyCtxt<'tcx>, visitor: &mut V) {
-  tcx
-    .hir()
-    .deep_visit_all_item_likes(&mut BodyFinder { tcx, visitor });
+pub struct EvalOptions {
+  pub configs: Vec<AnalysisConfig>,
+  pub compare_recursive: bool,
+  pub verify_determinism: bool,
+  pub record_slices: bool,
+  pub overlap_threshold: Option<f64>,
+  pub annotations: Vec<Annotation>,
 }
 
 pub struct EvalCrateVisitor {
-  count: usize,
-  total: usize,
+  num_bodies: usize,
+  options: EvalOptions,
+  pub progress: Progress,
   pub eval_results: Vec<EvalResult>,
+  pub recorded_slices: Vec<RecordedSlice>,
+  pub nondeterminism: Vec<Nondeterminism>,
+  pub slice_overlaps: Vec<SliceOverlap>,
+  pub trace: Trace,
 }
 
-impl BodyVisitor<'_> for EvalCrateVisitor {
-  fn visit(&mut self, body_span: Span, body_id: BodyId, tcx: TyCtxt) {
-    let source_map = tcx.sess.source_map();
-    let source_file = &source_map.lookup_source_file(body_span.lo());
-    if source_file.src.is_none() {
-      return;
+impl EvalCrateVisitor {
+  pub fn new(
+    progress: Progress,
+    trace: Trace,
+    num_bodies: usize,
+    options: EvalOptions,
+  ) -> Self {
+    EvalCrateVisitor {
+      num_bodies,
+      options,
+      progress,
+      trace,
+      eval_results: Vec::new(),
+      recorded_slices: Vec::new(),
+      nondeterminism: Vec::new(),
+      slice_overlaps: Vec::new(),
     }
+  }
 
-    let function_range = &match Range::from_span(body_span, source_map) {
-      Ok(range) => range,
-      Err(_) => {
-        return;
-      }
-    };
-
-    self.count += 1;
-
+  pub fn analyze(&mut self, tcx: TyCtxt, candidate: &CandidateBody) {
+    let source_map = tcx.sess.source_map();
+    let body_id = candidate.body_id;
+    let function_range = &candidate.function_range;
+    let function_path = &candidate.function_path;
     let local_def_id = tcx.hir().body_owner_def_id(body_id);
-    let def_id = local_def_id.to_def_id();
-    let function_path = &tcx.def_path_debug_str(def_id);
-
-    let only_run = env::var("ONLY_RUN");
-    if let Ok(n) = only_run {
-      let skip = match n.parse::<usize>() {
-        Ok(n) => self.count != n,
-        Err(_) => function_path != &n,
-      };
-      if skip {
-        return;
-      }
-    }
 
     info!(
       "Visiting {} ({} / {})",
-      function_path, self.count, self.total
+      function_path, candidate.index, self.num_bodies
     );
+    self.progress.start_function(function_path);
+    let function_start = Instant::now();
+    let path = Some(function_path.as_str());
 
     let start = Instant::now();
     let body_with_facts = borrowck_facts::get_body_with_borrowck_facts(tcx, local_def_id);
     let facts_duration = start.elapsed().as_secs_f64();
+    self
+      .trace
+      .complete("analysis", "borrowck_facts", start, path);
     let body = &body_with_facts.body;
     let num_instructions = body.all_locations().count();
+    let features = FunctionFeatures::compute(body);
 
     let body_span = tcx.hir().body(body_id).value.span;
     let start = Instant::now();
-    let tokens = Tokens::build(tcx, body_span, self.count);
+    let tokens = Tokens::build(tcx, body_span, candidate.index);
     let build_duration = start.elapsed().as_secs_f64();
+    self
+      .trace
+      .complete("analysis", "Tokens::build", start, path);
     let num_tokens = tokens.total_tokens();
 
     let span_lines = |sp: Span| {
@@ -207,95 +277,300 @@ impl BodyVisitor<'_> for EvalCrateVisitor {
     body_lines.sort();
     let num_lines = body_lines.len();
 
-    let start = Instant::now();
+    let statements = Statements::build(tcx, body_id, &tokens.token_spans);
+    let num_statements = statements.num_statements();
+
     fluid_let::fluid_set!(flowistry_ide::FOCUS_DEBUG, true);
-    let focus = flowistry_ide::focus(tcx, body_id).unwrap();
-    let duration = start.elapsed().as_secs_f64();
 
-    let start = Instant::now();
-    let eval_results = focus.place_info.into_iter().flat_map(|place_info| {
-      [Direction::Forward, Direction::Backward, Direction::Both]
-        .into_iter()
-        .map(|direction| {
-          let slice = match direction {
-            Direction::Both => place_info.slice.iter(),
-            Direction::Forward => place_info.forward.as_ref().unwrap().iter(),
-            Direction::Backward => place_info.backward.as_ref().unwrap().iter(),
-          };
-          let spans = slice.map(|range| range.to_span(tcx).unwrap());
+    let count_relevant = |slice: &[Range]| {
+      let spans = slice.iter().map(|range| range.to_span(tcx).unwrap());
+      let mut relevant_tokens = Vec::from_iter(tokens.query(spans));
+      relevant_tokens.sort_by_key(|(_, idx)| *idx);
+
+      let mut relevant_lines = relevant_tokens
+        .iter()
+        .flat_map(|(span, _)| span_lines(span.span()))
+        .collect::<Vec<_>>();
+      relevant_lines.dedup();
+      relevant_lines.sort();
+
+      let num_relevant_statements =
+        statements.count_relevant(relevant_tokens.iter().map(|(_, idx)| *idx));
+
+      (
+        relevant_tokens.len(),
+        relevant_lines,
+        num_relevant_statements,
+      )
+    };
 
-          let mut relevant_tokens = Vec::from_iter(tokens.query(spans));
-          relevant_tokens.sort_by_key(|(_, idx)| *idx);
-          let num_relevant_tokens = relevant_tokens.len();
+    let count_baseline = |indices: &HashSet<usize>| {
+      let lines = indices
+        .iter()
+        .flat_map(|idx| span_lines(tokens.token_spans[*idx]))
+        .collect::<HashSet<_>>();
+      (indices.len(), lines.len())
+    };
 
-          let mut relevant_lines = relevant_tokens
-            .iter()
-            .flat_map(|(span, _)| span_lines(span.span()))
-            .collect::<Vec<_>>();
-          relevant_lines.dedup();
-          relevant_lines.sort();
-
-          let num_relevant_lines = relevant_lines.len();
-
-          let n = num_relevant_lines;
-          let line_iqr = if n > 0 {
-            let lo = relevant_lines[n * 1 / 4];
-            let hi = relevant_lines[n * 3 / 4];
-            body_lines.iter().filter(|i| lo <= **i && **i <= hi).count()
-          } else {
-            0
+    let mut analyze_duration = 0.;
+    let mut output_duration = 0.;
+    let mut failed = false;
+    for config in &self.options.configs {
+      let start = Instant::now();
+      let focus = {
+        fluid_let::fluid_set!(EVAL_MODE, config.eval_mode);
+        flowistry_ide::focus(tcx, body_id)
+      };
+      let focus = match focus {
+        Ok(focus) => focus,
+        Err(e) => {
+          warn!(
+            "Failed to analyze {function_path} under config {}: {e:?}",
+            config.name
+          );
+          failed = true;
+          continue;
+        }
+      };
+      let duration = start.elapsed().as_secs_f64();
+      analyze_duration += duration;
+      self
+        .trace
+        .complete("analysis", &format!("focus ({})", config.name), start, path);
+
+      if self.options.record_slices || self.options.verify_determinism {
+        let slices =
+          RecordedSlice::from_place_info(function_path, &config.name, &focus.place_info);
+
+        if self.options.verify_determinism {
+          let rerun = {
+            fluid_let::fluid_set!(EVAL_MODE, config.eval_mode);
+            flowistry_ide::focus(tcx, body_id)
           };
-
-          EvalResult {
-            // function-level data
-            function_range: function_range.clone(),
-            function_path: function_path.clone(),
-            num_instructions,
-            num_tokens,
-            num_lines,
-            //
-            // sample-level parameters
-            range: place_info.range.clone(),
-            direction,
-            //
-            // sample-level data
-            num_relevant_tokens,
-            num_relevant_lines,
-            line_iqr,
-            duration,
+          match rerun {
+            Ok(rerun) => {
+              let rerun_slices = RecordedSlice::from_place_info(
+                function_path,
+                &config.name,
+                &rerun.place_info,
+              );
+              let differences = determinism::compare(&slices, &rerun_slices);
+              if !differences.is_empty() {
+                warn!(
+                  "{} slices of {function_path} differ between runs under config {}",
+                  differences.len(),
+                  config.name
+                );
+              }
+              self.nondeterminism.extend(differences);
+            }
+            Err(e) => warn!(
+              "Failed to rerun {function_path} under config {} to verify \
+               determinism: {e:?}",
+              config.name
+            ),
           }
-        })
-        .collect::<Vec<_>>()
-    });
+        }
 
-    self.eval_results.extend(eval_results);
-    let output_duration = start.elapsed().as_secs_f64();
-    info!("facts={facts_duration:.3} build={build_duration:.3} analyze={duration:.3} output={output_duration:.3}");
-  }
-}
+        if self.options.record_slices {
+          self.recorded_slices.extend(slices);
+        }
+      }
 
-impl EvalCrateVisitor {
-  pub fn new(total: usize) -> Self {
-    EvalCrateVisitor {
-      count: 0,
-      total,
-      eval_results: Vec::new(),
-    }
-  }
-}
+      if let Some(threshold) = self.options.overlap_threshold {
+        for direction in [Direction::Forward, Direction::Backward, Direction::Both] {
+          let slices = focus
+            .place_info
+            .iter()
+            .map(|info| {
+              let spans = direction_slice(info, direction)
+                .iter()
+                .map(|range| range.to_span(tcx).unwrap());
+              let indices = tokens
+                .query(spans)
+                .into_iter()
+                .map(|(_, idx)| *idx)
+                .collect::<HashSet<_>>();
+              (info.range.clone(), indices)
+            })
+            .collect::<Vec<_>>();
+          self.slice_overlaps.push(SliceOverlap::compute(
+            function_path,
+            &config.name,
+            direction,
+            &slices,
+            threshold,
+          ));
+        }
+      }
 
-pub struct ItemCounter {
-  pub count: usize,
-}
+      // Whole-program slices are only computed on request, since recursing into
+      // callees is much slower. If the interprocedural analysis fails for a body
+      // (e.g. a callee has no MIR available), the modular results are kept.
+      let recursive_start = Instant::now();
+      let recursive = (self.options.compare_recursive
+        && config.eval_mode.context_mode != ContextMode::Recurse)
+        .then(|| {
+          let start = Instant::now();
+          fluid_let::fluid_set!(EVAL_MODE, EvalMode {
+            context_mode: ContextMode::Recurse,
+            ..config.eval_mode
+          });
+          let focus = flowistry_ide::focus(tcx, body_id).ok()?;
+          Some((focus, start.elapsed().as_secs_f64()))
+        })
+        .flatten();
+      let (recursive_place_info, recursive_duration) = match recursive {
+        Some((focus, duration)) => (Some(focus.place_info), Some(duration)),
+        None => (None, None),
+      };
+      // The two analyses need not return the same places in the same order, so
+      // places are matched by their range.
+      let recursive_by_range = recursive_place_info.as_ref().map(|infos| {
+        infos
+          .iter()
+          .map(|info| (&info.range, info))
+          .collect::<HashMap<_, _>>()
+      });
+      let num_unmatched_recursive = recursive_by_range.as_ref().map(|by_range| {
+        let modular_ranges = focus
+          .place_info
+          .iter()
+          .map(|info| &info.range)
+          .collect::<HashSet<_>>();
+        let only_modular = modular_ranges
+          .iter()
+          .filter(|range| !by_range.contains_key(*range))
+          .count();
+        let only_recursive = by_range
+          .keys()
+          .filter(|range| !modular_ranges.contains(*range))
+          .count();
+        only_modular + only_recursive
+      });
+      if recursive_duration.is_some() {
+        self.trace.complete(
+          "analysis",
+          &format!("focus ({}, recursive)", config.name),
+          recursive_start,
+          path,
+        );
+      }
 
-impl BodyVisitor<'_> for ItemCounter {
-  fn visit(&mut self, body_span: Span, body_id: BodyId, tcx: TyCtxt) {
-    let source_map = tcx.sess.source_map();
-    let source_file = &source_map.lookup_source_file(body_span.lo());
-    if source_file.src.is_none() {
-      return;
+      let start = Instant::now();
+      let eval_results = focus.place_info.iter().flat_map(|place_info| {
+        let recursive_info = recursive_by_range
+          .as_ref()
+          .and_then(|by_range| by_range.get(&place_info.range).copied());
+
+        let criterion = tokens
+          .query([place_info.range.to_span(tcx).unwrap()])
+          .into_iter()
+          .map(|(_, idx)| *idx)
+          .collect::<HashSet<_>>();
+        let criterion_lines = span_lines(place_info.range.to_span(tcx).unwrap());
+        let identifier_counts =
+          count_baseline(&baseline::same_identifier(&tokens.idents, &criterion));
+
+        [Direction::Forward, Direction::Backward, Direction::Both]
+          .into_iter()
+          .map(|direction| {
+            let (num_relevant_tokens, relevant_lines, num_relevant_statements) =
+              count_relevant(direction_slice(place_info, direction));
+            let num_relevant_lines = relevant_lines.len();
+            let distances =
+              LineDistances::compute(criterion_lines.clone(), &relevant_lines);
+            let quality =
+              annotations::find(&self.options.annotations, &place_info.range, direction)
+                .map(|annotated| {
+                  // Annotations are 1-indexed, line indices are 0-indexed.
+                  let slice_lines = relevant_lines
+                    .iter()
+                    .map(|line| line + 1)
+                    .collect::<BTreeSet<_>>();
+                  Quality::compute(&slice_lines, annotated)
+                });
+
+            let n = num_relevant_lines;
+            let line_iqr = if n > 0 {
+              let lo = relevant_lines[n * 1 / 4];
+              let hi = relevant_lines[n * 3 / 4];
+              body_lines.iter().filter(|i| lo <= **i && **i <= hi).count()
+            } else {
+              0
+            };
+
+            let recursive_counts = recursive_info.map(|info| {
+              let (num_tokens, lines, _) =
+                count_relevant(direction_slice(info, direction));
+              (num_tokens, lines.len())
+            });
+
+            let def_use_counts = count_baseline(&baseline::def_use_closure(
+              &tokens.idents,
+              &tokens.statements,
+              &criterion,
+              direction,
+            ));
+
+            EvalResult {
+              schema_version: SCHEMA_VERSION,
+              //
+              // function-level data
+              function_range: function_range.clone(),
+              function_path: function_path.clone(),
+              stable_id: Some(candidate.stable_id.clone()),
+              content_hash: Some(candidate.content_hash.clone()),
+              num_instructions,
+              features: Some(features.clone()),
+              num_tokens,
+              num_lines,
+              num_statements: Some(num_statements),
+              //
+              // sample-level parameters
+              range: place_info.range.clone(),
+              config: config.name.clone(),
+              direction,
+              //
+              // sample-level data
+              num_relevant_tokens,
+              num_relevant_lines,
+              num_relevant_statements: Some(num_relevant_statements),
+              line_iqr,
+              max_line_distance: Some(distances.max),
+              mean_line_distance: Some(distances.mean),
+              num_relevant_lines_before: Some(distances.num_before),
+              num_relevant_lines_after: Some(distances.num_after),
+              precision: quality.and_then(|quality| quality.precision),
+              recall: quality.and_then(|quality| quality.recall),
+              f1: quality.and_then(|quality| quality.f1),
+              duration,
+              //
+              // whole-program comparison
+              num_relevant_tokens_recursive: recursive_counts.map(|(tokens, _)| tokens),
+              num_relevant_lines_recursive: recursive_counts.map(|(_, lines)| lines),
+              recursive_duration,
+              num_unmatched_recursive,
+              //
+              // textual baselines
+              num_baseline_identifier_tokens: Some(identifier_counts.0),
+              num_baseline_identifier_lines: Some(identifier_counts.1),
+              num_baseline_def_use_tokens: Some(def_use_counts.0),
+              num_baseline_def_use_lines: Some(def_use_counts.1),
+            }
+          })
+          .collect::<Vec<_>>()
+      });
+
+      self.eval_results.extend(eval_results);
+      output_duration += start.elapsed().as_secs_f64();
+      self.trace.complete("output", "output", start, path);
     }
 
-    self.count += 1;
+    info!("facts={facts_duration:.3} build={build_duration:.3} analyze={analyze_duration:.3} output={output_duration:.3}");
+    self
+      .trace
+      .complete("function", function_path, function_start, path);
+    self.progress.finish_function(!failed);
   }
 }
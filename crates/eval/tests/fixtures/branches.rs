@@ -0,0 +1,25 @@
+pub fn sum_positive(values: &[i32]) -> (i32, usize) {
+  let mut sum = 0;
+  let mut skipped = 0;
+  for value in values {
+    if *value > 0 {
+      sum += value;
+    } else {
+      skipped += 1;
+    }
+  }
+  (sum, skipped)
+}
+
+pub fn classify(n: u32) -> &'static str {
+  let label = match n % 3 {
+    0 => "fizz",
+    1 => "one",
+    _ => "other",
+  };
+  let mut count = 0;
+  while count < n {
+    count += 2;
+  }
+  if count > 10 { label } else { "small" }
+}
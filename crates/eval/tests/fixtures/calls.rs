@@ -0,0 +1,12 @@
+fn helper(x: &mut i32, y: i32) -> i32 {
+  *x += y;
+  y * 2
+}
+
+pub fn caller() -> (i32, i32) {
+  let mut x = 1;
+  let y = 2;
+  let z = helper(&mut x, y);
+  let add = |n: i32| n + z;
+  (add(x), y)
+}
@@ -0,0 +1,23 @@
+pub struct Counter {
+  pub hits: usize,
+  pub misses: usize,
+}
+
+impl Counter {
+  pub fn record(&mut self, hit: bool) {
+    let field = if hit { &mut self.hits } else { &mut self.misses };
+    *field += 1;
+  }
+
+  pub fn ratio(&self) -> f64 {
+    let total = self.hits + self.misses;
+    self.hits as f64 / total as f64
+  }
+}
+
+pub fn swap_first(v: &mut Vec<String>, s: String) -> Option<String> {
+  let first = v.first_mut()?;
+  let old = std::mem::replace(first, s);
+  v.push(old.clone());
+  Some(old)
+}
@@ -0,0 +1,6 @@
+pub fn straight_line(x: i32, y: i32) -> i32 {
+  let a = x + 1;
+  let b = y * 2;
+  let c = a - 3;
+  c + a
+}
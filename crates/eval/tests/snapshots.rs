@@ -0,0 +1,116 @@
+//! Snapshot tests of the eval metrics. Every program in `tests/fixtures` is evaluated
+//! with [`eval_source`], and the deterministic fields of its results are compared
+//! with `tests/snapshots/<fixture>.json`.
+//!
+//! When the slicer legitimately changes, rerun with `BLESS=1` to overwrite the
+//! snapshots, and review the diff before committing. A fixture without a committed
+//! snapshot fails until one is written the same way.
+
+use std::{env, fs, path::Path};
+
+use anyhow::{Context, Result};
+use flowistry::{infoflow::Direction, source_map::Range};
+use flowistry_eval::{eval_source, AnalysisConfig, EvalResult};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Snapshot {
+  stable_id: Option<String>,
+  range: Range,
+  direction: Direction,
+  num_tokens: usize,
+  num_lines: usize,
+  line_iqr: usize,
+  num_relevant_tokens: usize,
+  num_relevant_lines: usize,
+  num_relevant_statements: Option<usize>,
+}
+
+impl From<EvalResult> for Snapshot {
+  fn from(result: EvalResult) -> Self {
+    Snapshot {
+      stable_id: result.stable_id,
+      range: result.range,
+      direction: result.direction,
+      num_tokens: result.num_tokens,
+      num_lines: result.num_lines,
+      line_iqr: result.line_iqr,
+      num_relevant_tokens: result.num_relevant_tokens,
+      num_relevant_lines: result.num_relevant_lines,
+      num_relevant_statements: result.num_relevant_statements,
+    }
+  }
+}
+
+/// Checks one fixture, returning a description of the mismatch if any.
+fn check_fixture(
+  fixture: &Path,
+  snapshot_path: &Path,
+  bless: bool,
+) -> Result<Option<String>> {
+  let source = fs::read_to_string(fixture)?;
+  let results = eval_source(&source, vec![AnalysisConfig::modular()])?;
+  let mut actual = results.into_iter().map(Snapshot::from).collect::<Vec<_>>();
+  actual.sort_by_key(|row| (row.range.start, row.range.end, row.direction as u8));
+
+  if bless {
+    fs::write(snapshot_path, serde_json::to_string_pretty(&actual)? + "\n")?;
+    return Ok(None);
+  }
+  if !snapshot_path.exists() {
+    return Ok(Some(format!("no snapshot at {}", snapshot_path.display())));
+  }
+
+  let expected: Vec<Snapshot> =
+    serde_json::from_str(&fs::read_to_string(snapshot_path)?)?;
+  if actual == expected {
+    return Ok(None);
+  }
+
+  let mut diff = Vec::new();
+  if actual.len() != expected.len() {
+    diff.push(format!(
+      "expected {} rows, found {}",
+      expected.len(),
+      actual.len()
+    ));
+  }
+  for (expected, actual) in expected.iter().zip(&actual) {
+    if expected != actual {
+      diff.push(format!("  expected {expected:?}\n     found {actual:?}"));
+    }
+  }
+  Ok(Some(diff.join("\n")))
+}
+
+#[test]
+fn snapshots() -> Result<()> {
+  let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+  let bless = env::var("BLESS").is_ok();
+
+  let mut fixtures = fs::read_dir(root.join("fixtures"))?
+    .map(|entry| Ok(entry?.path()))
+    .collect::<Result<Vec<_>>>()?;
+  fixtures.sort();
+  if bless {
+    fs::create_dir_all(root.join("snapshots"))?;
+  }
+
+  let mut failures = Vec::new();
+  for fixture in fixtures {
+    let name = fixture.file_stem().unwrap().to_string_lossy().to_string();
+    let snapshot_path = root.join("snapshots").join(format!("{name}.json"));
+    if let Some(diff) = check_fixture(&fixture, &snapshot_path, bless)
+      .with_context(|| format!("in fixture {name}"))?
+    {
+      failures.push(format!("{name}:\n{diff}"));
+    }
+  }
+
+  assert!(
+    failures.is_empty(),
+    "snapshots differ or are missing, run with BLESS=1 to write them:\n{}",
+    failures.join("\n")
+  );
+  Ok(())
+}